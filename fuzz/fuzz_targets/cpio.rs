@@ -0,0 +1,18 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+
+use std::io;
+
+use index_repo::cpio;
+
+fuzz_target!(|data: &[u8]| {
+    let archive = cpio::Archive::new(data, Vec::new());
+    for entry in archive.entries() {
+        match entry {
+            Ok(mut entry) => {
+                let _ = io::copy(&mut entry, &mut io::sink());
+            }
+            Err(_) => break,
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+
+use index_repo::rpm;
+
+fuzz_target!(|data: &[u8]| {
+    let mut r = data;
+    if let Ok((_, header)) = rpm::read_full_header_sync(&mut r, 0) {
+        for tag in header.index_entries.keys() {
+            let _ = header.get_tag(*tag);
+        }
+        let _ = rpm::PackageMetadata::from_header(&header);
+        let _ = rpm::FileInfo::from_header(&header);
+        let _ = rpm::Dependency::from_header(&header);
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+
+use index_repo::rpm;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = rpm::parse_index_entry(data);
+});
@@ -0,0 +1,19 @@
+DROP INDEX packages_buildtime_index;
+DROP INDEX packages_vendor_index;
+CREATE TABLE packages_tmp
+(
+  id      INTEGER NOT NULL PRIMARY KEY,
+  repo_id INTEGER NOT NULL,
+  name    VARCHAR NOT NULL,
+  arch    VARCHAR NOT NULL,
+  version VARCHAR NOT NULL,
+  epoch   VARCHAR NOT NULL,
+  release VARCHAR NOT NULL,
+  FOREIGN KEY (repo_id) REFERENCES repos (id)
+);
+INSERT INTO packages_tmp
+SELECT id, repo_id, name, arch, version, epoch, release
+FROM packages;
+DROP TABLE packages;
+ALTER TABLE packages_tmp
+  RENAME TO packages;
@@ -0,0 +1,24 @@
+ALTER TABLE packages
+  ADD COLUMN summary VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN description VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN license VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN url VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN vendor VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN packager VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN buildhost VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN buildtime BIGINT;
+ALTER TABLE packages
+  ADD COLUMN sourcerpm VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN package_group VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN installed_size BIGINT;
+CREATE INDEX packages_vendor_index ON packages (vendor);
+CREATE INDEX packages_buildtime_index ON packages (buildtime);
@@ -0,0 +1,18 @@
+DROP INDEX files_package_id_index;
+DROP INDEX files_name_index;
+CREATE TABLE files_tmp
+(
+  id         INTEGER NOT NULL PRIMARY KEY,
+  name       VARCHAR NOT NULL,
+  package_id INTEGER NOT NULL,
+  FOREIGN KEY (package_id) REFERENCES packages (id)
+);
+INSERT INTO files_tmp
+SELECT id, name, package_id
+FROM files;
+DROP TABLE files;
+ALTER TABLE files_tmp
+  RENAME TO files;
+UPDATE files
+SET name = '.' || name
+WHERE substr(name, 1, 1) = '/';
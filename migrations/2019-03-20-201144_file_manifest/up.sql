@@ -0,0 +1,26 @@
+ALTER TABLE files
+  ADD COLUMN mode INTEGER;
+ALTER TABLE files
+  ADD COLUMN size BIGINT;
+ALTER TABLE files
+  ADD COLUMN user_name VARCHAR;
+ALTER TABLE files
+  ADD COLUMN group_name VARCHAR;
+ALTER TABLE files
+  ADD COLUMN mtime BIGINT;
+ALTER TABLE files
+  ADD COLUMN digest VARCHAR;
+ALTER TABLE files
+  ADD COLUMN link_to VARCHAR;
+ALTER TABLE files
+  ADD COLUMN flags INTEGER;
+-- Older versions stored cpio archive names, e.g. ./usr/bin/true, while file
+-- manifests use absolute paths, e.g. /usr/bin/true.
+UPDATE files
+SET name = '/' || substr(name, 3)
+WHERE substr(name, 1, 2) = './';
+UPDATE files
+SET name = '/' || name
+WHERE substr(name, 1, 1) != '/';
+CREATE INDEX files_name_index ON files (name);
+CREATE INDEX files_package_id_index ON files (package_id);
@@ -0,0 +1,31 @@
+CREATE TABLE packages_tmp
+(
+  id             INTEGER NOT NULL PRIMARY KEY,
+  repo_id        INTEGER NOT NULL,
+  name           VARCHAR NOT NULL,
+  arch           VARCHAR NOT NULL,
+  version        VARCHAR NOT NULL,
+  epoch          VARCHAR NOT NULL,
+  release        VARCHAR NOT NULL,
+  summary        VARCHAR,
+  description    VARCHAR,
+  license        VARCHAR,
+  url            VARCHAR,
+  vendor         VARCHAR,
+  packager       VARCHAR,
+  buildhost      VARCHAR,
+  buildtime      BIGINT,
+  sourcerpm      VARCHAR,
+  package_group  VARCHAR,
+  installed_size BIGINT,
+  FOREIGN KEY (repo_id) REFERENCES repos (id)
+);
+INSERT INTO packages_tmp
+SELECT id, repo_id, name, arch, version, epoch, release, summary, description, license, url,
+       vendor, packager, buildhost, buildtime, sourcerpm, package_group, installed_size
+FROM packages;
+DROP TABLE packages;
+ALTER TABLE packages_tmp
+  RENAME TO packages;
+CREATE INDEX packages_vendor_index ON packages (vendor);
+CREATE INDEX packages_buildtime_index ON packages (buildtime);
@@ -0,0 +1,4 @@
+ALTER TABLE packages
+  ADD COLUMN signature_key_id VARCHAR;
+ALTER TABLE packages
+  ADD COLUMN signature_status VARCHAR;
@@ -0,0 +1,13 @@
+CREATE TABLE dependencies
+(
+  id         INTEGER NOT NULL PRIMARY KEY,
+  package_id INTEGER NOT NULL,
+  kind       VARCHAR NOT NULL,
+  name_id    INTEGER NOT NULL,
+  flags      INTEGER NOT NULL,
+  version    VARCHAR NOT NULL,
+  FOREIGN KEY (package_id) REFERENCES packages (id),
+  FOREIGN KEY (name_id) REFERENCES strings (id)
+);
+CREATE INDEX dependencies_name_id_index ON dependencies (name_id);
+CREATE INDEX dependencies_package_id_index ON dependencies (package_id);
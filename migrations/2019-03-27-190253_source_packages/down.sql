@@ -0,0 +1,8 @@
+DROP VIEW source_packages;
+DROP INDEX packages_sourcerpm_index;
+DROP TRIGGER spec_files_fts_update;
+DROP TRIGGER spec_files_fts_delete;
+DROP TRIGGER spec_files_fts_insert;
+DROP TABLE spec_files_fts;
+DROP TABLE spec_files;
+DROP TABLE source_files;
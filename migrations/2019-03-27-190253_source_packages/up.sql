@@ -0,0 +1,64 @@
+CREATE TABLE source_files
+(
+  id         INTEGER NOT NULL PRIMARY KEY,
+  package_id INTEGER NOT NULL,
+  kind       VARCHAR NOT NULL,
+  name       VARCHAR NOT NULL,
+  size       BIGINT,
+  digest     VARCHAR,
+  FOREIGN KEY (package_id) REFERENCES packages (id)
+);
+CREATE INDEX source_files_package_id_index ON source_files (package_id);
+CREATE INDEX source_files_name_index ON source_files (name);
+CREATE TABLE spec_files
+(
+  id         INTEGER NOT NULL PRIMARY KEY,
+  package_id INTEGER NOT NULL,
+  name       VARCHAR NOT NULL,
+  content    VARCHAR NOT NULL,
+  FOREIGN KEY (package_id) REFERENCES packages (id)
+);
+CREATE INDEX spec_files_package_id_index ON spec_files (package_id);
+-- Full-text index of spec file contents. The contents are stored only once,
+-- in spec_files, and triggers keep the index in sync with them.
+CREATE VIRTUAL TABLE spec_files_fts USING fts5
+(
+  content,
+  content = 'spec_files',
+  content_rowid = 'id'
+);
+CREATE TRIGGER spec_files_fts_insert
+  AFTER INSERT
+  ON spec_files
+BEGIN
+  INSERT INTO spec_files_fts (rowid, content)
+  VALUES (new.id, new.content);
+END;
+CREATE TRIGGER spec_files_fts_delete
+  AFTER DELETE
+  ON spec_files
+BEGIN
+  INSERT INTO spec_files_fts (spec_files_fts, rowid, content)
+  VALUES ('delete', old.id, old.content);
+END;
+CREATE TRIGGER spec_files_fts_update
+  AFTER UPDATE
+  ON spec_files
+BEGIN
+  INSERT INTO spec_files_fts (spec_files_fts, rowid, content)
+  VALUES ('delete', old.id, old.content);
+  INSERT INTO spec_files_fts (rowid, content)
+  VALUES (new.id, new.content);
+END;
+CREATE INDEX packages_sourcerpm_index ON packages (sourcerpm);
+-- Source packages have no SOURCERPM, binary packages refer to the file name
+-- of the source package they were built from.
+CREATE VIEW source_packages AS
+SELECT binary_packages.id AS package_id,
+       source_packages.id AS source_package_id
+FROM packages AS binary_packages
+       INNER JOIN packages AS source_packages
+                  ON binary_packages.sourcerpm =
+                     source_packages.name || '-' || source_packages.version || '-' ||
+                     source_packages.release || '.src.rpm'
+WHERE source_packages.sourcerpm IS NULL;
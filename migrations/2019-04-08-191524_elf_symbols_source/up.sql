@@ -0,0 +1,3 @@
+-- Symbols that were indexed so far all come from .dynsym.
+ALTER TABLE elf_symbols
+  ADD COLUMN source VARCHAR NOT NULL DEFAULT 'dynsym';
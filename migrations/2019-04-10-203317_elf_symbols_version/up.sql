@@ -0,0 +1,6 @@
+ALTER TABLE elf_symbols
+  ADD COLUMN version VARCHAR;
+ALTER TABLE elf_symbols
+  ADD COLUMN version_default BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE elf_symbols
+  ADD COLUMN version_hidden BOOLEAN NOT NULL DEFAULT 0;
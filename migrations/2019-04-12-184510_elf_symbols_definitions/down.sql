@@ -0,0 +1,22 @@
+DROP INDEX elf_symbols_name_id_index;
+CREATE TABLE elf_symbols_tmp
+(
+  id              INTEGER NOT NULL PRIMARY KEY,
+  file_id         INTEGER NOT NULL,
+  name_id         INTEGER NOT NULL,
+  st_info         INTEGER NOT NULL,
+  st_other        INTEGER NOT NULL,
+  source          VARCHAR NOT NULL DEFAULT 'dynsym',
+  version         VARCHAR,
+  version_default BOOLEAN NOT NULL DEFAULT 0,
+  version_hidden  BOOLEAN NOT NULL DEFAULT 0,
+  FOREIGN KEY (file_id) REFERENCES files (id),
+  FOREIGN KEY (name_id) REFERENCES strings (id)
+);
+INSERT INTO elf_symbols_tmp
+SELECT id, file_id, name_id, st_info, st_other, source, version, version_default, version_hidden
+FROM elf_symbols;
+DROP TABLE elf_symbols;
+ALTER TABLE elf_symbols_tmp
+  RENAME TO elf_symbols;
+CREATE INDEX elf_symbols_name_id_index ON elf_symbols (name_id);
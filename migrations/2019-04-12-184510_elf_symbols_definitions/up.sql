@@ -0,0 +1,9 @@
+-- These are unknown for symbols that were indexed before.
+ALTER TABLE elf_symbols
+  ADD COLUMN st_shndx INTEGER;
+ALTER TABLE elf_symbols
+  ADD COLUMN st_value BIGINT;
+ALTER TABLE elf_symbols
+  ADD COLUMN st_size BIGINT;
+ALTER TABLE elf_symbols
+  ADD COLUMN defined BOOLEAN;
@@ -0,0 +1 @@
+DROP TABLE elf_dynamic_entries;
@@ -0,0 +1,11 @@
+CREATE TABLE elf_dynamic_entries
+(
+  id       INTEGER NOT NULL PRIMARY KEY,
+  file_id  INTEGER NOT NULL,
+  kind     VARCHAR NOT NULL,
+  value_id INTEGER NOT NULL,
+  FOREIGN KEY (file_id) REFERENCES files (id),
+  FOREIGN KEY (value_id) REFERENCES strings (id)
+);
+CREATE INDEX elf_dynamic_entries_file_id_index ON elf_dynamic_entries (file_id);
+CREATE INDEX elf_dynamic_entries_value_id_index ON elf_dynamic_entries (value_id);
@@ -0,0 +1,15 @@
+CREATE TABLE elf_files
+(
+  id          INTEGER NOT NULL PRIMARY KEY,
+  file_id     INTEGER NOT NULL,
+  machine     VARCHAR NOT NULL,
+  class       INTEGER NOT NULL,
+  endianness  VARCHAR NOT NULL,
+  elf_type    VARCHAR NOT NULL,
+  os_abi      VARCHAR NOT NULL,
+  interpreter VARCHAR,
+  pie         BOOLEAN NOT NULL,
+  FOREIGN KEY (file_id) REFERENCES files (id)
+);
+CREATE INDEX elf_files_file_id_index ON elf_files (file_id);
+CREATE INDEX elf_files_machine_index ON elf_files (machine);
@@ -0,0 +1,26 @@
+DROP INDEX files_build_id_index;
+DROP INDEX files_package_id_index;
+DROP INDEX files_name_index;
+CREATE TABLE files_tmp
+(
+  id         INTEGER NOT NULL PRIMARY KEY,
+  name       VARCHAR NOT NULL,
+  package_id INTEGER NOT NULL,
+  mode       INTEGER,
+  size       BIGINT,
+  user_name  VARCHAR,
+  group_name VARCHAR,
+  mtime      BIGINT,
+  digest     VARCHAR,
+  link_to    VARCHAR,
+  flags      INTEGER,
+  FOREIGN KEY (package_id) REFERENCES packages (id)
+);
+INSERT INTO files_tmp
+SELECT id, name, package_id, mode, size, user_name, group_name, mtime, digest, link_to, flags
+FROM files;
+DROP TABLE files;
+ALTER TABLE files_tmp
+  RENAME TO files;
+CREATE INDEX files_name_index ON files (name);
+CREATE INDEX files_package_id_index ON files (package_id);
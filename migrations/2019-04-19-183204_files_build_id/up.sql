@@ -0,0 +1,3 @@
+ALTER TABLE files
+  ADD COLUMN build_id VARCHAR;
+CREATE INDEX files_build_id_index ON files (build_id);
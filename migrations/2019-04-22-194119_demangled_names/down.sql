@@ -0,0 +1 @@
+DROP TABLE demangled_names;
@@ -0,0 +1,9 @@
+CREATE TABLE demangled_names
+(
+  name_id        INTEGER NOT NULL PRIMARY KEY,
+  demangled      VARCHAR NOT NULL,
+  qualified_name VARCHAR NOT NULL,
+  FOREIGN KEY (name_id) REFERENCES strings (id)
+);
+CREATE INDEX demangled_names_demangled_index ON demangled_names (demangled);
+CREATE INDEX demangled_names_qualified_name_index ON demangled_names (qualified_name);
@@ -3,7 +3,10 @@
 #[macro_use]
 extern crate index_repo;
 
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use arrayref::array_ref;
@@ -12,26 +15,31 @@ use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, cr
 use diesel::prelude::*;
 use diesel_migrations::run_pending_migrations;
 use dotenv::dotenv;
-use failure::{Error, format_err, ResultExt};
+use failure::{bail, Error, format_err, ResultExt};
 use futures::future::{Future, join_all};
 use futures::Stream;
 use log::{debug, info, warn};
+use memmap::Mmap;
 use tokio::io::AsyncRead;
 use tokio_executor::DefaultExecutor;
+use tokio_io::io::Window;
 use tokio_sync::semaphore::Semaphore;
 
 use index_repo::clap::{database_url_arg, database_url_value};
 use index_repo::cpio;
 use index_repo::db;
 use index_repo::decoders::Decoder;
+use index_repo::elf;
 use index_repo::errors::FutureExt;
 use index_repo::fs::create_file_all;
 use index_repo::hashes;
 use index_repo::http;
+use index_repo::limits::{limits, set_limits};
 use index_repo::metrics::{log_metrics, monitor_metrics, timed_result, update_metrics};
 use index_repo::models::*;
 use index_repo::repomd;
 use index_repo::rpm;
+use index_repo::signatures::{check_signatures, Keyring, SignatureStatus};
 use index_repo::sync::semaphore_acquire;
 
 async fn fetch_repomd<'a>(
@@ -74,6 +82,13 @@ async fn fetch_file<'a>(
     Ok(path)
 }
 
+#[derive(Clone)]
+struct Options {
+    keyring: Arc<Keyring>,
+    require_signature: bool,
+    stream: bool,
+}
+
 fn with_connection<F: FnOnce(&SqliteConnection) -> Result<T, Error>, T>(
     conn: &Mutex<SqliteConnection>,
     f: F,
@@ -91,53 +106,111 @@ fn with_connection<F: FnOnce(&SqliteConnection) -> Result<T, Error>, T>(
     Ok(result)
 }
 
-async fn index_elf_file<'a>(
+/// `elf_bytes` is either the file in memory or a mapping of its spooled
+/// copy, in which case only the parts that goblin looks at are paged in.
+async fn index_elf_file<'a, B: AsRef<[u8]>>(
     conn: &'a Mutex<SqliteConnection>,
     package_id: i32,
-    name: &'a str,
-    elf_bytes: Vec<u8>,
+    names: &'a [String],
+    elf_bytes: B,
 ) -> Result<(), Error> {
-    let elf = match goblin::Object::parse(&elf_bytes) {
+    let elf_bytes = elf_bytes.as_ref();
+    let elf = match goblin::Object::parse(elf_bytes) {
         Ok(goblin::Object::Elf(t)) => t,
         _ => return Ok(()), // ignore errors - peek() could have been mistaken
     };
-    let elf_symbols = elf.dynsyms
-        .iter()
-        .flat_map(|sym| match elf.dynstrtab.get(sym.st_name) {
-            Some(Ok(name)) =>
-                Some((name, i32::from(sym.st_info), i32::from(sym.st_other))),
-            _ => {
-                warn!("Could not resolve an ELF symbol name");
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+    let elf_properties = elf::properties(&elf);
+    let build_id = elf::build_id(&elf, elf_bytes);
+    let elf_symbols = elf::symbols(&elf, elf_bytes);
+    let elf_dynamic_entries = elf::dynamic_entries(&elf);
     await!(index_repo::tokio::blocking(|| {
         with_connection(conn, |conn| {
             conn.transaction(|| -> Result<(), Error> {
-                db::persist_elf_symbols(&conn, package_id, name, elf_symbols)
+                db::persist_elf_file(&conn, package_id, names, &elf_properties)?;
+                if let Some(build_id) = &build_id {
+                    db::persist_build_id(&conn, package_id, names, build_id)?;
+                }
+                db::persist_elf_symbols(&conn, package_id, names, elf_symbols)?;
+                db::persist_elf_dynamic_entries(&conn, package_id, names, &elf_dynamic_entries)
             })
         })
     }))?;
     Ok(())
 }
 
+async fn index_spec_file<'a>(
+    conn: &'a Mutex<SqliteConnection>,
+    package_id: i32,
+    name: &'a str,
+    spec_bytes: Vec<u8>,
+) -> Result<(), Error> {
+    let content = String::from_utf8_lossy(&spec_bytes);
+    await!(index_repo::tokio::blocking(|| {
+        with_connection(conn, |conn| {
+            db::persist_spec_file(&conn, package_id, name, &content)
+        })
+    }))?;
+    Ok(())
+}
+
+/// Copies the entry to an anonymous temporary file and maps it.
+async fn spool_entry_data<'a, A: AsyncRead + Send + 'static>(
+    a: A, pos: usize, header: &'a cpio::Header, peek: Window<Vec<u8>>,
+) -> Result<(A, usize, Mmap), Error> {
+    let file = tempfile::tempfile().context("Could not create a temporary file")?;
+    let (a, pos, file) = await!(cpio::copy_entry_data(
+        a, pos, header, peek, BufWriter::new(file)))?;
+    let file = file.into_inner().map_err(|e| format_err!("{}", e.error()))
+        .context("Could not write a temporary file")?;
+    // The file is private to this process, so nobody can change it under the mapping
+    let map = unsafe { Mmap::map(&file) }.context("Could not map a temporary file")?;
+    update_metrics(|metrics| {
+        metrics.spooled_files_count += 1;
+        metrics.spooled_files_size.v += header.c_filesize;
+    })?;
+    Ok((a, pos, map))
+}
+
 async fn index_file<A: AsyncRead + Send + 'static>(
     conn: &Mutex<SqliteConnection>,
     package_id: i32,
     a: A,
     pos: usize,
     entry: cpio::Entry,
+    names: Vec<String>,
+    spec_paths: &HashSet<String>,
 ) -> Result<(A, usize), Error> {
+    let path = cpio::entry_path(&entry.name);
+    if spec_paths.contains(&path) {
+        if entry.header.c_filesize > limits().max_entry_data_size {
+            warn!("Skipping {}: spec file is too large ({} bytes)", path, entry.header.c_filesize);
+            return await!(cpio::skip_entry_data(a, pos, entry.header.c_filesize, entry.peek));
+        }
+        let (a, pos, spec_bytes) = await!(cpio::read_entry_data(
+            a, pos, &entry.header, entry.peek))?;
+        await!(index_spec_file(conn, package_id, &path, spec_bytes))?;
+        return Ok((a, pos));
+    }
     if entry.header.c_filesize < 16 {
         return Ok((a, pos));
     }
     match goblin::peek_bytes(array_ref![entry.peek.get_ref(), 0, 16]) {
         Ok(goblin::Hint::Elf(_)) => {
-            let (a, pos, elf_bytes) = await!(cpio::read_entry_data(
-                a, pos, entry.header.c_filesize, entry.peek))?;
-            await!(index_elf_file(conn, package_id, &entry.name, elf_bytes))?;
-            Ok((a, pos))
+            let paths = names
+                .iter()
+                .map(|name| cpio::entry_path(name))
+                .collect::<Vec<_>>();
+            if entry.header.c_filesize > limits().spool_threshold {
+                let (a, pos, elf_map) = await!(spool_entry_data(
+                    a, pos, &entry.header, entry.peek))?;
+                await!(index_elf_file(conn, package_id, &paths, elf_map))?;
+                Ok((a, pos))
+            } else {
+                let (a, pos, elf_bytes) = await!(cpio::read_entry_data(
+                    a, pos, &entry.header, entry.peek))?;
+                await!(index_elf_file(conn, package_id, &paths, elf_bytes))?;
+                Ok((a, pos))
+            }
         }
         _ => {
             let (a, pos) = await!(cpio::skip_entry_data(
@@ -147,6 +220,54 @@ async fn index_file<A: AsyncRead + Send + 'static>(
     }
 }
 
+fn open_at(path: &Path, pos: u64) -> Result<BufReader<File>, Error> {
+    let mut file = File::open(path)
+        .with_context(|_| format!("File::open({:?}) failed", path))?;
+    file.seek(SeekFrom::Start(pos))
+        .with_context(|_| format!("Could not seek in {:?}", path))?;
+    Ok(BufReader::new(file))
+}
+
+async fn index_payload<'a>(
+    conn: &'a Mutex<SqliteConnection>,
+    package_id: i32,
+    mut a: rpm::Payload,
+    manifest: &'a [rpm::FileInfo],
+    spec_paths: &'a HashSet<String>,
+    package_uri: &'a str,
+    verifier: rpm::Verifier,
+) -> Result<(), Error> {
+    let mut pos = 0;
+    let mut hardlinks = cpio::Hardlinks::default();
+    loop {
+        let (local_a, local_pos, entry) = await!(cpio::read_entry_start(a, pos, manifest))?;
+        let entry = match entry {
+            Some(t) => t,
+            None => {
+                a = local_a;
+                break;
+            }
+        };
+        debug!("Indexing file {}:{}...", package_uri, &entry.name);
+        let names = hardlinks.add(&entry);
+        let (local_a, local_pos) = await!(index_file(
+            conn, package_id, local_a, local_pos, entry, names, spec_paths))?;
+        let (local_a, local_pos) = await!(cpio::read_entry_end(local_a, local_pos))?;
+        a = local_a;
+        pos = local_pos;
+    }
+    await!(rpm::verify_payload(a, verifier))
+}
+
+fn check_package_digest(digest: &hashes::SharedDigest, expected: &str) -> Result<(), Error> {
+    let hexdigest = digest.hexdigest()?;
+    if hexdigest != expected {
+        bail!("Package {} checksum mismatch: expected {}, got {}",
+              digest.hash_type(), expected, hexdigest);
+    }
+    Ok(())
+}
+
 async fn index_package(
     conn: Arc<Mutex<SqliteConnection>>,
     repo_id: i32,
@@ -154,40 +275,98 @@ async fn index_package(
     http_semaphore: Arc<Semaphore>,
     io_semaphore: Arc<Semaphore>,
     repo_uri: String,
+    options: Options,
     p: RpmPackage,
 ) -> Result<(), Error> {
-    let path = await!(fetch_file(
-        &client,
-        &http_semaphore,
-        &io_semaphore,
-        repo_uri.clone(),
-        p.location_href.clone(),
-        repomd::Checksum {
-            tpe: p.checksum_type.to_owned(),
-            hexdigest: p.pkg_id.to_owned(),
-        }))?;
-    info!("Indexing package {}/{}...", &repo_uri, &p.location_href);
-    let file = await_old!(tokio::fs::File::open(path.clone())
-        .with_context(move |_| format!("Could not open {:?}", path)))?;
+    let package_uri = repo_uri.to_owned() + "/" + &p.location_href;
+    // Streamed packages hold their HTTP connections until they are indexed
+    let _io_guard = if options.stream {
+        Some(await!(semaphore_acquire(&io_semaphore))?)
+    } else {
+        None
+    };
+    let (headers, path, package_digest) = if options.stream {
+        let uri = package_uri.parse::<hyper::Uri>()
+            .with_context(|_| format!("Malformed URI: {}", package_uri))?;
+        let response = await!(http::checked_fetch(&client, &http_semaphore, uri))?;
+        info!("Indexing package {}...", &package_uri);
+        let package_digest = hashes::SharedDigest::new(&p.checksum_type)?;
+        let body = hashes::DigestReader::new(
+            http::BodyReader::new(response.into_body()), vec![package_digest.clone()]);
+        (await!(rpm::read_all_headers(body))?, None, Some(package_digest))
+    } else {
+        let path = await!(fetch_file(
+            &client,
+            &http_semaphore,
+            &io_semaphore,
+            repo_uri.clone(),
+            p.location_href.clone(),
+            repomd::Checksum {
+                tpe: p.checksum_type.to_owned(),
+                hexdigest: p.pkg_id.to_owned(),
+            }))?;
+        info!("Indexing package {}...", &package_uri);
+        let file = await_old!(tokio::fs::File::open(path.clone())
+            .with_context({
+                let path = path.clone();
+                move |_| format!("Could not open {:?}", path)
+            }))?;
+        (await!(rpm::read_all_headers(file))?, Some(path), None)
+    };
+    let (a, pos, _lead, signature_header, header, verifier) = headers;
+    let header_pos = (pos - header.size()) as u64;
+    let signature = await!(index_repo::tokio::blocking(|| {
+        // The legacy header+payload signatures cannot be checked when streaming
+        let header_and_payload = match &path {
+            Some(path) => Some(open_at(path, header_pos)?),
+            None => None,
+        };
+        Ok(check_signatures(&options.keyring, &signature_header, &header, header_and_payload))
+    }))?;
+    if options.require_signature && signature.status != SignatureStatus::Valid {
+        bail!("Package signature is {} (key ID: {})",
+              signature.status.as_str(),
+              signature.key_id.as_ref().map_or("none", String::as_str));
+    }
+    let metadata = rpm::PackageMetadata::from_header(&header)?;
+    let manifest = rpm::FileInfo::from_header(&header)?;
+    let dependencies = rpm::Dependency::from_header(&header)?;
+    let (source_files, spec_paths) = if header.is_source() {
+        let source_files = rpm::SourceFile::from_header(&header, &manifest)?;
+        let spec_paths = manifest
+            .iter()
+            .filter(|f| f.flags & rpm::FILE_SPECFILE != 0)
+            .map(|f| f.path.clone())
+            .collect::<HashSet<_>>();
+        (source_files, spec_paths)
+    } else {
+        (Vec::new(), HashSet::new())
+    };
     let package_id = await!(index_repo::tokio::blocking(|| {
         with_connection(&conn, |conn| {
-            db::persist_package(conn, repo_id, &p)
+            conn.transaction(|| -> Result<i32, Error> {
+                let package_id = db::persist_package(
+                    conn, repo_id, &p, &metadata, &signature)?;
+                db::persist_file_manifest(conn, package_id, &manifest)?;
+                db::persist_dependencies(conn, package_id, &dependencies)?;
+                db::persist_source_files(conn, package_id, &source_files)?;
+                Ok(package_id)
+            })
         })
     }))?;
-    let (mut a, _pos, _lead, _signature_header, _header) = await!(rpm::read_all_headers(file))?;
-    let mut pos = 0;
-    loop {
-        let (local_a, local_pos, entry) = await!(cpio::read_entry_start(a, pos))?;
-        let entry = match entry {
-            Some(t) => t,
-            None => break,
-        };
-        debug!("Indexing file {}/{}:{}...", &repo_uri, &p.location_href, &entry.name);
-        let (local_a, local_pos) = await!(index_file(
-            &conn, package_id, local_a, local_pos, entry))?;
-        let (local_a, local_pos) = await!(cpio::read_entry_end(local_a, local_pos))?;
-        a = local_a;
-        pos = local_pos;
+    let result = await!(index_payload(
+        &conn, package_id, a, &manifest, &spec_paths, &package_uri, verifier))
+        .and_then(|()| match &package_digest {
+            Some(digest) => check_package_digest(digest, &p.pkg_id),
+            None => Ok(()),
+        });
+    if let Err(e) = result {
+        await!(index_repo::tokio::blocking(|| {
+            with_connection(&conn, |conn| {
+                conn.transaction(|| db::delete_package(conn, package_id))
+            })
+        }))?;
+        return Err(e);
     }
     update_metrics(|metrics| {
         metrics.indexed_packages_count += 1;
@@ -203,6 +382,7 @@ async fn index_repo(
     arches: Option<Vec<String>>,
     requirements: Option<Vec<String>>,
     jobs: usize,
+    options: Options,
 ) -> Result<(), Error> {
     info!("Indexing repo {}...", &repo_uri);
     let http_semaphore = Arc::new(Semaphore::new(jobs));
@@ -238,6 +418,7 @@ async fn index_repo(
     let index_packages = join_all(packages
         .into_iter()
         .map(move |package| {
+            let package_uri = repo_uri.to_owned() + "/" + &package.location_href;
             let future = index_package(
                 conn.clone(),
                 repo_id,
@@ -245,8 +426,17 @@ async fn index_repo(
                 http_semaphore.clone(),
                 io_semaphore.clone(),
                 repo_uri.clone(),
+                options.clone(),
                 package);
-            let compat_future = tokio_async_await::compat::backward::Compat::new(future);
+            let compat_future = tokio_async_await::compat::backward::Compat::new(future)
+                .or_else(move |e| {
+                    let e = Error::from(e.context(format!(
+                        "Failed to index package {}", package_uri)));
+                    warn!("{}", index_repo::errors::format(&e));
+                    update_metrics(|metrics| {
+                        metrics.failed_packages_count += 1;
+                    })
+                });
             futures::sync::oneshot::spawn(compat_future, &DefaultExecutor::current())
         }));
     await_old!(index_packages)?;
@@ -269,6 +459,28 @@ async fn bootstrap() -> Result<(), Error> {
             .short("j")
             .long("jobs")
             .default_value("1"))
+        .arg(Arg::with_name("KEYRING")
+            .long("keyring")
+            .takes_value(true))
+        .arg(Arg::with_name("REQUIRE_SIGNATURE")
+            .long("require-signature")
+            .requires("KEYRING"))
+        .arg(Arg::with_name("MAX_HEADER_SIZE")
+            .long("max-header-size")
+            .takes_value(true)
+            .help("Reject packages whose RPM headers declare larger stores"))
+        .arg(Arg::with_name("MAX_ENTRY_SIZE")
+            .long("max-entry-size")
+            .takes_value(true)
+            .help("Skip files that are larger when they need to be read into memory"))
+        .arg(Arg::with_name("SPOOL_THRESHOLD")
+            .long("spool-threshold")
+            .takes_value(true)
+            .help("Spool larger ELF files to disk instead of reading them into memory, \
+                   must not exceed --max-entry-size"))
+        .arg(Arg::with_name("STREAM")
+            .long("stream")
+            .help("Index packages while downloading them, without saving them to disk"))
         .arg(Arg::with_name("URI")
             .required(true)
             .index(1))
@@ -279,6 +491,34 @@ async fn bootstrap() -> Result<(), Error> {
     let jobs = matches.value_of("JOBS").unwrap().parse::<usize>()
         .context("Malformed -j/--jobs value")?;
     let repo_uri = matches.value_of("URI").unwrap();
+    let keyring = match matches.value_of("KEYRING") {
+        Some(t) => Keyring::load_dir(Path::new(t))?,
+        None => Keyring::default(),
+    };
+    let mut limits = limits();
+    if let Some(t) = matches.value_of("MAX_HEADER_SIZE") {
+        limits.max_store_size = t.parse::<u32>()
+            .context("Malformed --max-header-size value")?;
+    }
+    if let Some(t) = matches.value_of("MAX_ENTRY_SIZE") {
+        limits.max_entry_data_size = t.parse::<u64>()
+            .context("Malformed --max-entry-size value")?;
+    }
+    if let Some(t) = matches.value_of("SPOOL_THRESHOLD") {
+        limits.spool_threshold = t.parse::<u64>()
+            .context("Malformed --spool-threshold value")?;
+    }
+    // Smaller ELF files are read into memory
+    if limits.spool_threshold > limits.max_entry_data_size {
+        bail!("--spool-threshold {} exceeds --max-entry-size {}",
+              limits.spool_threshold, limits.max_entry_data_size);
+    }
+    set_limits(limits)?;
+    let options = Options {
+        keyring: Arc::new(keyring),
+        require_signature: matches.is_present("REQUIRE_SIGNATURE"),
+        stream: matches.is_present("STREAM"),
+    };
     let conn = SqliteConnection::establish(&database_url)
         .context(format!("SqliteConnection::establish({}) failed", database_url))?;
     let cache_size = 1024 * 1024 * 1024;
@@ -291,7 +531,8 @@ async fn bootstrap() -> Result<(), Error> {
             .map_err(|e| {
                 warn!("{}", index_repo::errors::format(&e));
             }));
-    await!(index_repo(conn, client, repo_uri.to_owned(), arches, requirements, jobs))?;
+    await!(index_repo(
+        conn, client, repo_uri.to_owned(), arches, requirements, jobs, options))?;
     log_metrics()?;
     Ok(())
 }
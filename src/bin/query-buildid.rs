@@ -0,0 +1,93 @@
+use std::io::{self, BufRead};
+use std::time::Instant;
+
+use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use diesel_migrations::run_pending_migrations;
+use dotenv::dotenv;
+use failure::{Error, ResultExt};
+use prettytable::{cell, row, Table};
+
+use index_repo::clap::{database_url_arg, database_url_value};
+use index_repo::schema::*;
+
+/// Accepts both bare build ids and `eu-unstrip -n --core` lines, where the
+/// build id is the first hex word, followed by `@` and its address.
+fn parse_build_id(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .map(|word| word.split('@').next().unwrap_or(word))
+        .find(|word| !word.is_empty() && word.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+fn read_build_ids() -> Result<Vec<String>, Error> {
+    let stdin = io::stdin();
+    let mut build_ids = Vec::new();
+    for line in stdin.lock().lines() {
+        build_ids.extend(parse_build_id(&line.context("Failed to read stdin")?));
+    }
+    Ok(build_ids)
+}
+
+fn nevra(name: &str, epoch: &str, version: &str, release: &str, arch: &str) -> String {
+    match epoch {
+        "" | "0" => format!("{}-{}-{}.{}", name, version, release, arch),
+        _ => format!("{}-{}:{}-{}.{}", name, epoch, version, release, arch),
+    }
+}
+
+fn main() -> Result<(), Error> {
+    dotenv().ok();
+    let matches = app_from_crate!()
+        .arg(database_url_arg())
+        .arg(Arg::with_name("BUILD_ID")
+            .required(true)
+            .index(1)
+            .multiple(true)
+            .help("Build id in hex, or - to read build ids or eu-unstrip -n output from stdin"))
+        .get_matches();
+    let database_url = database_url_value(&matches);
+    let mut build_ids = Vec::new();
+    for arg in matches.values_of_lossy("BUILD_ID").unwrap() {
+        if arg == "-" {
+            build_ids.extend(read_build_ids()?);
+        } else {
+            build_ids.push(arg.to_lowercase());
+        }
+    }
+    let conn = SqliteConnection::establish(&database_url)
+        .context(format!("SqliteConnection::establish({}) failed", database_url))?;
+    run_pending_migrations(&conn)
+        .context("run_pending_migrations() failed")?;
+    let t0 = Instant::now();
+    let query = files::table
+        .inner_join(packages::table)
+        .filter(files::build_id.eq_any(build_ids))
+        .select((
+            files::build_id,
+            packages::name,
+            packages::epoch,
+            packages::version,
+            packages::release,
+            packages::arch,
+            files::name,
+        ));
+    println!("sql> {}", debug_query::<Sqlite, _>(&query));
+    let rows = query
+        .load::<(Option<String>, String, String, String, String, String, String)>(&conn)
+        .context("Failed to query a build id")?;
+    let t = Instant::now() - t0;
+    let len = rows.len();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Build ID", "Package", "File"]);
+    for (build_id, name, epoch, version, release, arch, file) in rows {
+        let package = nevra(&name, &epoch, &version, &release, &arch);
+        table.add_row(row![build_id.unwrap_or_default(), package, file]);
+    };
+    table.printstd();
+    println!("{} rows retrieved in {:?}", len, t);
+    Ok(())
+}
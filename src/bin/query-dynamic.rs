@@ -0,0 +1,68 @@
+use std::time::Instant;
+
+use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use diesel_migrations::run_pending_migrations;
+use dotenv::dotenv;
+use failure::{Error, ResultExt};
+use prettytable::{cell, row, Table};
+
+use index_repo::clap::{database_url_arg, database_url_value};
+use index_repo::elf;
+use index_repo::schema::*;
+
+fn main() -> Result<(), Error> {
+    dotenv().ok();
+    let matches = app_from_crate!()
+        .arg(database_url_arg())
+        .arg(Arg::with_name("kind")
+            .long("kind")
+            .takes_value(true)
+            .possible_values(&[
+                elf::DYNAMIC_NEEDED,
+                elf::DYNAMIC_SONAME,
+                elf::DYNAMIC_RPATH,
+                elf::DYNAMIC_RUNPATH,
+            ])
+            .help("Only look at dynamic entries of this kind"))
+        .arg(Arg::with_name("VALUE")
+            .required(true)
+            .index(1)
+            .multiple(true)
+            .help("Library name, soname or search path list, e.g. libssl.so.1.1"))
+        .get_matches();
+    let database_url = database_url_value(&matches);
+    let values = matches.values_of_lossy("VALUE").unwrap();
+    let conn = SqliteConnection::establish(&database_url)
+        .context(format!("SqliteConnection::establish({}) failed", database_url))?;
+    run_pending_migrations(&conn)
+        .context("run_pending_migrations() failed")?;
+    let t0 = Instant::now();
+    let mut query = strings::table
+        .inner_join(elf_dynamic_entries::table
+            .inner_join(files::table
+                .inner_join(packages::table)))
+        .filter(strings::name.eq_any(values))
+        .select((packages::name, files::name, elf_dynamic_entries::kind, strings::name))
+        .into_boxed();
+    if let Some(kind) = matches.value_of("kind") {
+        query = query.filter(elf_dynamic_entries::kind.eq(kind));
+    }
+    println!("sql> {}", debug_query::<Sqlite, _>(&query));
+    let rows = query
+        .load::<(String, String, String, String)>(&conn)
+        .context("Failed to query a dynamic entry")?;
+    let t = Instant::now() - t0;
+    let len = rows.len();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Package", "File", "Kind", "Value"]);
+    for (package, file, kind, value) in rows {
+        table.add_row(row![package, file, kind, value]);
+    };
+    table.printstd();
+    println!("{} rows retrieved in {:?}", len, t);
+    Ok(())
+}
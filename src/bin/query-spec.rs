@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
+use diesel::debug_query;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Text};
+use diesel::sqlite::Sqlite;
+use diesel_migrations::run_pending_migrations;
+use dotenv::dotenv;
+use failure::{Error, ResultExt};
+use prettytable::{cell, row, Table};
+
+use index_repo::clap::{database_url_arg, database_url_value};
+use index_repo::schema::*;
+
+fn main() -> Result<(), Error> {
+    dotenv().ok();
+    let matches = app_from_crate!()
+        .arg(database_url_arg())
+        .arg(Arg::with_name("QUERY")
+            .required(true)
+            .index(1)
+            .help("SQLite FTS5 query, e.g. openssl or '\"%bcond_without tests\"'"))
+        .get_matches();
+    let database_url = database_url_value(&matches);
+    let text = matches.value_of("QUERY").unwrap();
+    let conn = SqliteConnection::establish(&database_url)
+        .context(format!("SqliteConnection::establish({}) failed", database_url))?;
+    run_pending_migrations(&conn)
+        .context("run_pending_migrations() failed")?;
+    let t0 = Instant::now();
+    let query = spec_files_fts::table
+        .inner_join(spec_files::table
+            .inner_join(packages::table))
+        .filter(sql::<Bool>("spec_files_fts MATCH ").bind::<Text, _>(text))
+        .select((
+            packages::name,
+            spec_files::name,
+            sql::<Text>("snippet(spec_files_fts, 0, '[', ']', '...', 16)"),
+        ));
+    println!("sql> {}", debug_query::<Sqlite, _>(&query));
+    let rows = query
+        .load::<(String, String, String)>(&conn)
+        .context("Failed to query spec files")?;
+    let t = Instant::now() - t0;
+    let len = rows.len();
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Package", "Spec", "Match"]);
+    for (package, spec, snippet) in rows {
+        table.add_row(row![package, spec, snippet]);
+    };
+    table.printstd();
+    println!("{} rows retrieved in {:?}", len, t);
+    Ok(())
+}
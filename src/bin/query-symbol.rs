@@ -3,6 +3,8 @@ use std::time::Instant;
 use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
 use diesel::debug_query;
 use diesel::prelude::*;
+use diesel::Queryable;
+use diesel::sql_types::Integer;
 use diesel::sqlite::Sqlite;
 use diesel_migrations::run_pending_migrations;
 use dotenv::dotenv;
@@ -10,8 +12,87 @@ use failure::{Error, ResultExt};
 use prettytable::{cell, row, Table};
 
 use index_repo::clap::{database_url_arg, database_url_value};
+use index_repo::db::glob;
+use index_repo::elf;
 use index_repo::schema::*;
 
+#[derive(Queryable)]
+struct Row {
+    package: String,
+    file: String,
+    symbol: String,
+    demangled: Option<String>,
+    version: Option<String>,
+    version_default: bool,
+    defined: Option<bool>,
+    source: String,
+}
+
+/// NAME, NAME@VERSION or NAME@@VERSION
+struct Pattern<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+    default_version: bool,
+}
+
+impl<'a> Pattern<'a> {
+    fn new(symbol: &'a str) -> Pattern<'a> {
+        match symbol.find('@') {
+            Some(i) if symbol[i..].starts_with("@@") => Pattern {
+                name: &symbol[..i],
+                version: Some(&symbol[i + 2..]),
+                default_version: true,
+            },
+            Some(i) => Pattern {
+                name: &symbol[..i],
+                version: Some(&symbol[i + 1..]),
+                default_version: false,
+            },
+            None => Pattern { name: symbol, version: None, default_version: false },
+        }
+    }
+
+    /// Qualified names match regardless of template arguments, so rows found by them are
+    /// checked again here.
+    fn matches(&self, row: &Row) -> bool {
+        let name_matches = row.symbol == self.name ||
+            row.demangled.as_ref().map(String::as_str) == Some(self.name) ||
+            elf::demangle_without_params(&row.symbol)
+                .map_or(false, |name| elf::template_args_match(self.name, &name));
+        let version_matches = match self.version {
+            Some(version) => row.version.as_ref().map(String::as_str) == Some(version) &&
+                (row.version_default || !self.default_version),
+            None => true,
+        };
+        name_matches && version_matches
+    }
+}
+
+fn mangled_name_ids(name: &str, wildcard: bool) -> strings::BoxedQuery<'_, Sqlite, Integer> {
+    let query = strings::table
+        .select(strings::id)
+        .into_boxed();
+    if wildcard {
+        query.filter(glob(strings::name, name))
+    } else {
+        query.filter(strings::name.eq(name))
+    }
+}
+
+fn demangled_name_ids(
+    name: &str, wildcard: bool,
+) -> demangled_names::BoxedQuery<'_, Sqlite, Integer> {
+    let query = demangled_names::table
+        .select(demangled_names::name_id)
+        .into_boxed();
+    if wildcard {
+        query.filter(glob(demangled_names::demangled, name))
+    } else {
+        query.filter(demangled_names::demangled.eq(name)
+            .or(demangled_names::qualified_name.eq(elf::qualified_name(name))))
+    }
+}
+
 fn main() -> Result<(), Error> {
     dotenv().ok();
     let matches = app_from_crate!()
@@ -19,32 +100,155 @@ fn main() -> Result<(), Error> {
         .arg(Arg::with_name("SYMBOL")
             .required(true)
             .index(1)
-            .multiple(true))
+            .multiple(true)
+            .help("NAME, NAME@VERSION or NAME@@VERSION (default version only), where NAME is \
+                   mangled or demangled, with or without parameters and trailing template \
+                   arguments, e.g. 'std::vector<int>::push_back'"))
+        .arg(Arg::with_name("wildcard")
+            .long("wildcard")
+            .help("Treat NAME as a case-sensitive GLOB pattern with *, ? and [...] wildcards, \
+                   e.g. 'std::vector<int[*]>::push_back*'"))
+        .arg(Arg::with_name("providers")
+            .long("providers")
+            .conflicts_with("consumers")
+            .help("Only show files that define the symbols, if that is known"))
+        .arg(Arg::with_name("consumers")
+            .long("consumers")
+            .help("Only show files that import the symbols, if that is known"))
+        .arg(Arg::with_name("machine")
+            .long("machine")
+            .takes_value(true)
+            .help("Only show ELF files for this e_machine, e.g. X86_64, 386 or BPF"))
+        .arg(Arg::with_name("class")
+            .long("class")
+            .takes_value(true)
+            .possible_values(&["32", "64"])
+            .help("Only show 32-bit or 64-bit ELF files"))
+        .arg(Arg::with_name("endianness")
+            .long("endianness")
+            .takes_value(true)
+            .possible_values(&[elf::ENDIANNESS_LITTLE, elf::ENDIANNESS_BIG])
+            .help("Only show ELF files with this byte order"))
+        .arg(Arg::with_name("type")
+            .long("type")
+            .takes_value(true)
+            .help("Only show ELF files with this e_type, e.g. EXEC, DYN or REL"))
+        .arg(Arg::with_name("os-abi")
+            .long("os-abi")
+            .takes_value(true)
+            .help("Only show ELF files for this OS ABI, e.g. SYSV or GNU"))
+        .arg(Arg::with_name("interpreter")
+            .long("interpreter")
+            .takes_value(true)
+            .help("Only show ELF files with this PT_INTERP"))
+        .arg(Arg::with_name("pie")
+            .long("pie")
+            .conflicts_with("no-pie")
+            .help("Only show position-independent executables"))
+        .arg(Arg::with_name("no-pie")
+            .long("no-pie")
+            .help("Only show files that are not position-independent executables"))
         .get_matches();
     let database_url = database_url_value(&matches);
     let symbols = matches.values_of_lossy("SYMBOL").unwrap();
+    let patterns: Vec<_> = symbols.iter().map(|symbol| Pattern::new(symbol)).collect();
+    let wildcard = matches.is_present("wildcard");
     let conn = SqliteConnection::establish(&database_url)
         .context(format!("SqliteConnection::establish({}) failed", database_url))?;
     run_pending_migrations(&conn)
         .context("run_pending_migrations() failed")?;
     let t0 = Instant::now();
-    let query = strings::table
+    let mut query = strings::table
+        .left_join(demangled_names::table)
         .inner_join(elf_symbols::table
             .inner_join(files::table
                 .inner_join(packages::table)))
-        .filter(strings::name.eq_any(symbols))
-        .select((packages::name, files::name, strings::name));
+        .select((
+            packages::name,
+            files::name,
+            strings::name,
+            demangled_names::demangled.nullable(),
+            elf_symbols::version,
+            elf_symbols::version_default,
+            elf_symbols::defined,
+            elf_symbols::source,
+        ))
+        .into_boxed();
+    for pattern in &patterns {
+        // Subqueries on strings::id let SQLite use the indices of both name columns. GLOB
+        // patterns use them only up to the first wildcard and scan when they start with one.
+        let name_matches = strings::id.eq_any(mangled_name_ids(pattern.name, wildcard))
+            .or(strings::id.eq_any(demangled_name_ids(pattern.name, wildcard)));
+        query = match pattern.version {
+            Some(version) if pattern.default_version => query.or_filter(name_matches
+                .and(elf_symbols::version.eq(version))
+                .and(elf_symbols::version_default.eq(true))),
+            Some(version) => query.or_filter(name_matches
+                .and(elf_symbols::version.eq(version))),
+            None => query.or_filter(name_matches),
+        };
+    }
+    if matches.is_present("providers") {
+        query = query.filter(elf_symbols::defined.eq(true));
+    } else if matches.is_present("consumers") {
+        query = query.filter(elf_symbols::defined.eq(false));
+    }
+    let mut elf_files_query = elf_files::table
+        .select(elf_files::file_id)
+        .into_boxed();
+    if let Some(machine) = matches.value_of("machine") {
+        elf_files_query = elf_files_query.filter(elf_files::machine.eq(machine));
+    }
+    if let Some(class) = matches.value_of("class") {
+        elf_files_query = elf_files_query.filter(elf_files::class.eq(class.parse::<i32>()?));
+    }
+    if let Some(endianness) = matches.value_of("endianness") {
+        elf_files_query = elf_files_query.filter(elf_files::endianness.eq(endianness));
+    }
+    if let Some(tpe) = matches.value_of("type") {
+        elf_files_query = elf_files_query.filter(elf_files::elf_type.eq(tpe));
+    }
+    if let Some(os_abi) = matches.value_of("os-abi") {
+        elf_files_query = elf_files_query.filter(elf_files::os_abi.eq(os_abi));
+    }
+    if let Some(interpreter) = matches.value_of("interpreter") {
+        elf_files_query = elf_files_query.filter(elf_files::interpreter.eq(interpreter));
+    }
+    if matches.is_present("pie") || matches.is_present("no-pie") {
+        elf_files_query = elf_files_query.filter(elf_files::pie.eq(matches.is_present("pie")));
+    }
+    // Files that were indexed before ELF headers were recorded only show up without these filters
+    let elf_file_args = [
+        "machine", "class", "endianness", "type", "os-abi", "interpreter", "pie", "no-pie",
+    ];
+    if elf_file_args.iter().any(|name| matches.is_present(name)) {
+        query = query.filter(elf_symbols::file_id.eq_any(elf_files_query));
+    }
     println!("sql> {}", debug_query::<Sqlite, _>(&query));
-    let rows = query
-        .load::<(String, String, String)>(&conn)
-        .context("Failed to query a symbol")?;
+    let rows: Vec<_> = query
+        .load::<Row>(&conn)
+        .context("Failed to query a symbol")?
+        .into_iter()
+        .filter(|row| wildcard || patterns.iter().any(|pattern| pattern.matches(row)))
+        .collect();
     let t = Instant::now() - t0;
     let len = rows.len();
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-    table.set_titles(row!["Package", "File", "Symbol"]);
-    for (package, file, symbol) in rows {
-        table.add_row(row![package, file, symbol]);
+    table.set_titles(row!["Package", "File", "Symbol", "Demangled", "Defined", "Table"]);
+    for row in rows {
+        let symbol = match row.version {
+            Some(version) if row.version_default => format!("{}@@{}", row.symbol, version),
+            Some(version) => format!("{}@{}", row.symbol, version),
+            None => row.symbol,
+        };
+        let defined = match row.defined {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "?",
+        };
+        let demangled = row.demangled.unwrap_or_default();
+        table.add_row(row![row.package, row.file, symbol, demangled, defined, row.source]);
     };
     table.printstd();
     println!("{} rows retrieved in {:?}", len, t);
@@ -0,0 +1,197 @@
+#![feature(async_await, await_macro, futures_api)]
+
+#[macro_use]
+extern crate index_repo;
+
+use std::fs::{self, File, Permissions};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+use std::str::from_utf8;
+
+use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
+use failure::{bail, Error, format_err, ResultExt};
+use filetime::{FileTime, set_file_times, set_symlink_file_times};
+use glob::Pattern;
+use log::warn;
+use tokio_io::AsyncRead;
+use tokio_io::io::{read, Window, write_all};
+
+use index_repo::cpio;
+use index_repo::fs::target_path;
+use index_repo::rpm::{self, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+
+/// Copies the decompressed payload to stdout as is.
+async fn write_cpio<A: AsyncRead + Send + 'static>(mut a: A) -> Result<A, Error> {
+    let mut out = tokio::io::stdout();
+    let mut buf = vec![0u8; 8192];
+    loop {
+        let (local_a, local_buf, n) = await_old!(read(a, buf))?;
+        if n == 0 {
+            break Ok(local_a);
+        }
+        let mut local_window = Window::new(local_buf);
+        local_window.set_end(n);
+        let (local_out, local_window) = await_old!(write_all(out, local_window))?;
+        a = local_a;
+        buf = local_window.into_inner();
+        out = local_out;
+    }
+}
+
+/// Makes room for a new file, but keeps existing directories.
+fn prepare_target(target: &Path) -> Result<(), Error> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|_| format!("create_dir_all({:?}) failed", parent))?;
+    }
+    if let Ok(metadata) = fs::symlink_metadata(target) {
+        if !metadata.is_dir() {
+            fs::remove_file(target)
+                .with_context(|_| format!("remove_file({:?}) failed", target))?;
+        }
+    }
+    Ok(())
+}
+
+fn set_mode(target: &Path, mode: u32) -> Result<(), Error> {
+    fs::set_permissions(target, Permissions::from_mode(mode & 0o7777))
+        .with_context(|_| format!("set_permissions({:?}) failed", target))?;
+    Ok(())
+}
+
+/// Writes the entry to `directory` under each of `names`. Returns the path of
+/// the directory that was created, if any, so that its mtime can be restored
+/// after all of its contents are in place.
+async fn extract_entry<'a, A: AsyncRead + Send + 'static>(
+    a: A, pos: usize, entry: cpio::Entry, names: &'a [String], directory: &'a Path,
+) -> Result<(A, usize, Option<(PathBuf, FileTime)>), Error> {
+    let header = &entry.header;
+    let mode = header.c_mode as u32;
+    let mtime = FileTime::from_unix_time(header.c_mtime as i64, 0);
+    let target = target_path(directory, &names[0])?;
+    match (mode & u32::from(S_IFMT)) as u16 {
+        S_IFDIR => {
+            fs::create_dir_all(&target)
+                .with_context(|_| format!("create_dir_all({:?}) failed", target))?;
+            set_mode(&target, mode)?;
+            let (a, pos) = await!(cpio::skip_entry_data(a, pos, header.c_filesize, entry.peek))?;
+            Ok((a, pos, Some((target, mtime))))
+        }
+        S_IFREG => {
+            prepare_target(&target)?;
+            let file = File::create(&target)
+                .with_context(|_| format!("File::create({:?}) failed", target))?;
+            let (a, pos, _) = await!(cpio::copy_entry_data(a, pos, header, entry.peek, file))?;
+            set_mode(&target, mode)?;
+            set_file_times(&target, mtime, mtime)
+                .with_context(|_| format!("set_file_times({:?}) failed", target))?;
+            for name in &names[1..] {
+                let link = target_path(directory, name)?;
+                prepare_target(&link)?;
+                fs::hard_link(&target, &link)
+                    .with_context(|_| format!("hard_link({:?}, {:?}) failed", target, link))?;
+            }
+            Ok((a, pos, None))
+        }
+        S_IFLNK => {
+            let (a, pos, data) = await!(cpio::read_entry_data(a, pos, header, entry.peek))?;
+            let link_to = from_utf8(&data)
+                .with_context(|_| format!("Malformed symlink target of {}", names[0]))?;
+            prepare_target(&target)?;
+            symlink(link_to, &target)
+                .with_context(|_| format!("symlink({:?}, {:?}) failed", link_to, target))?;
+            set_symlink_file_times(&target, mtime, mtime)
+                .with_context(|_| format!("set_symlink_file_times({:?}) failed", target))?;
+            Ok((a, pos, None))
+        }
+        _ => {
+            warn!("Skipping {}: unsupported file type {:o}", names[0], mode & u32::from(S_IFMT));
+            let (a, pos) = await!(cpio::skip_entry_data(a, pos, header.c_filesize, entry.peek))?;
+            Ok((a, pos, None))
+        }
+    }
+}
+
+async fn bootstrap() -> Result<(), Error> {
+    let matches = app_from_crate!()
+        .arg(Arg::with_name("directory")
+            .short("C")
+            .long("directory")
+            .value_name("DIR")
+            .default_value(".")
+            .help("Extract to DIR, or write the raw cpio payload to stdout if DIR is -"))
+        .arg(Arg::with_name("list")
+            .short("l")
+            .long("list")
+            .help("Only list the selected files"))
+        .arg(Arg::with_name("RPM")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("GLOB")
+            .index(2)
+            .multiple(true)
+            .help("Select only files whose absolute paths match, e.g. */libz.so.*"))
+        .get_matches();
+    let path = matches.value_of("RPM").unwrap();
+    let directory = Path::new(matches.value_of("directory").unwrap());
+    let list = matches.is_present("list");
+    let patterns = matches.values_of_lossy("GLOB")
+        .unwrap_or_default()
+        .iter()
+        .map(|glob| Pattern::new(glob).map_err(|e| format_err!("Malformed glob {}: {}", glob, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let file = await_old!(tokio::fs::File::open(path.to_owned()))?;
+    let (mut a, _pos, _lead, _signature_header, header, verifier) =
+        await!(rpm::read_all_headers(file))?;
+    if directory == Path::new("-") && !list {
+        if !patterns.is_empty() {
+            bail!("Globs cannot be used when writing the raw cpio payload");
+        }
+        let a = await!(write_cpio(a))?;
+        return await!(rpm::verify_payload(a, verifier));
+    }
+    let manifest = rpm::FileInfo::from_header(&header)?;
+    let mut pos = 0;
+    let mut hardlinks = cpio::Hardlinks::default();
+    let mut directories = Vec::new();
+    loop {
+        let (local_a, local_pos, entry) = await!(cpio::read_entry_start(a, pos, &manifest))?;
+        let entry = match entry {
+            Some(t) => t,
+            None => {
+                a = local_a;
+                break;
+            }
+        };
+        let names = hardlinks.add(&entry)
+            .iter()
+            .map(|name| cpio::entry_path(name))
+            .filter(|name| patterns.is_empty() || patterns.iter().any(|p| p.matches(name)))
+            .collect::<Vec<_>>();
+        let (local_a, local_pos) = if names.is_empty() || list {
+            for name in &names {
+                println!("{}", name);
+            }
+            await!(cpio::skip_entry_data(
+                local_a, local_pos, entry.header.c_filesize, entry.peek))?
+        } else {
+            let (local_a, local_pos, created) = await!(extract_entry(
+                local_a, local_pos, entry, &names, directory))?;
+            directories.extend(created);
+            (local_a, local_pos)
+        };
+        let (local_a, local_pos) = await!(cpio::read_entry_end(local_a, local_pos))?;
+        a = local_a;
+        pos = local_pos;
+    }
+    for (target, mtime) in directories.iter().rev() {
+        set_file_times(target, *mtime, *mtime)
+            .with_context(|_| format!("set_file_times({:?}) failed", target))?;
+    }
+    await!(rpm::verify_payload(a, verifier))
+}
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    index_repo::tokio::main(tokio_async_await::compat::backward::Compat::new(bootstrap()))
+}
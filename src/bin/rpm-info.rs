@@ -0,0 +1,177 @@
+#![feature(async_await, await_macro, futures_api)]
+
+#[macro_use]
+extern crate index_repo;
+
+use clap::{app_from_crate, Arg, crate_authors, crate_description, crate_name, crate_version};
+use failure::Error;
+use prettytable::{cell, row, Table};
+use serde_json::{json, Value};
+
+use index_repo::rpm::{self, FullHeader, Lead, TagValue};
+
+fn lead_name(lead: &Lead) -> String {
+    let len = lead.name.iter().position(|b| *b == 0).unwrap_or(lead.name.len());
+    String::from_utf8_lossy(&lead.name[..len]).into_owned()
+}
+
+fn lead_to_json(lead: &Lead) -> Value {
+    json!({
+        "magic": hex::encode(lead.magic),
+        "major": lead.major,
+        "minor": lead.minor,
+        "type": lead.tpe,
+        "archnum": lead.archnum,
+        "name": lead_name(lead),
+        "osnum": lead.osnum,
+        "signature_type": lead.signature_type,
+    })
+}
+
+fn print_lead(lead: &Lead) {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Lead field", "Value"]);
+    table.add_row(row!["magic", hex::encode(lead.magic)]);
+    table.add_row(row!["major", lead.major]);
+    table.add_row(row!["minor", lead.minor]);
+    table.add_row(row!["type", lead.tpe]);
+    table.add_row(row!["archnum", lead.archnum]);
+    table.add_row(row!["name", lead_name(lead)]);
+    table.add_row(row!["osnum", lead.osnum]);
+    table.add_row(row!["signature_type", lead.signature_type]);
+    table.printstd();
+}
+
+fn value_to_json(value: &TagValue) -> Value {
+    match value {
+        TagValue::Null => Value::Null,
+        TagValue::Char(t) | TagValue::Int8(t) => json!(t),
+        TagValue::Int16(t) => json!(t),
+        TagValue::Int32(t) => json!(t),
+        TagValue::Int64(t) => json!(t),
+        TagValue::String(t) => json!(t),
+        TagValue::Bin(t) => json!(hex::encode(t)),
+        TagValue::StringArray(t) | TagValue::I18nString(t) => json!(t),
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn value_to_string(value: &TagValue) -> String {
+    match value {
+        TagValue::Null => String::new(),
+        TagValue::Char(t) | TagValue::Int8(t) => join(t),
+        TagValue::Int16(t) => join(t),
+        TagValue::Int32(t) => join(t),
+        TagValue::Int64(t) => join(t),
+        TagValue::String(t) => t.clone(),
+        TagValue::Bin(t) => hex::encode(t),
+        TagValue::StringArray(t) | TagValue::I18nString(t) => t.join("\n"),
+    }
+}
+
+struct Entry {
+    tag: u32,
+    name: &'static str,
+    tpe: &'static str,
+    count: u32,
+    value: Result<Option<TagValue>, Error>,
+}
+
+/// Index entries sorted by tag, `tag_name` tells which table the tags belong to.
+fn sorted_entries(header: &FullHeader, tag_name: fn(u32) -> Option<&'static str>) -> Vec<Entry> {
+    let mut tags = header.index_entries.keys().cloned().collect::<Vec<_>>();
+    tags.sort();
+    tags.into_iter()
+        .map(|tag| {
+            let entry = &header.index_entries[&tag];
+            Entry {
+                tag,
+                name: tag_name(tag).unwrap_or("?"),
+                tpe: rpm::type_name(entry.tpe).unwrap_or("?"),
+                count: entry.count,
+                value: header.get_tag(tag),
+            }
+        })
+        .collect()
+}
+
+fn header_to_json(header: &FullHeader, tag_name: fn(u32) -> Option<&'static str>) -> Value {
+    sorted_entries(header, tag_name)
+        .into_iter()
+        .map(|entry| {
+            let mut json = json!({
+                "tag": entry.tag,
+                "name": entry.name,
+                "type": entry.tpe,
+                "count": entry.count,
+            });
+            match entry.value {
+                Ok(t) => json["value"] = t.as_ref().map_or(Value::Null, value_to_json),
+                Err(e) => json["error"] = json!(e.to_string()),
+            }
+            json
+        })
+        .collect()
+}
+
+fn print_header(title: &str, header: &FullHeader, tag_name: fn(u32) -> Option<&'static str>) {
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row![title, "Name", "Type", "Count", "Value"]);
+    for entry in sorted_entries(header, tag_name) {
+        let value = match entry.value {
+            Ok(t) => t.as_ref().map(value_to_string).unwrap_or_default(),
+            Err(e) => format!("<{}>", e),
+        };
+        table.add_row(row![entry.tag, entry.name, entry.tpe, entry.count, value]);
+    }
+    table.printstd();
+}
+
+fn payload_tag(header: &FullHeader, tag: u32, default: &str) -> String {
+    header.get_string_tag(tag, default).unwrap_or_else(|e| format!("<{}>", e))
+}
+
+async fn bootstrap() -> Result<(), Error> {
+    let matches = app_from_crate!()
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Print JSON instead of tables"))
+        .arg(Arg::with_name("RPM")
+            .required(true)
+            .index(1))
+        .get_matches();
+    let path = matches.value_of("RPM").unwrap();
+    let file = await_old!(tokio::fs::File::open(path.to_owned()))?;
+    let (file, pos, lead) = await!(rpm::read_lead(file, 0))?;
+    let (file, pos, signature_header) = await!(rpm::read_full_header(file, pos))?;
+    let (_file, _pos, header) = await!(rpm::read_full_header(file, pos))?;
+    let payload_format = payload_tag(&header, rpm::TAG_PAYLOADFORMAT, "cpio");
+    let payload_coding = payload_tag(&header, rpm::TAG_PAYLOADCOMPRESSOR, "gzip");
+    if matches.is_present("json") {
+        let info = json!({
+            "lead": lead_to_json(&lead),
+            "signature_header": header_to_json(&signature_header, rpm::signature_tag_name),
+            "header": header_to_json(&header, rpm::tag_name),
+            "payload_format": payload_format,
+            "payload_coding": payload_coding,
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        print_lead(&lead);
+        print_header("Signature tag", &signature_header, rpm::signature_tag_name);
+        print_header("Header tag", &header, rpm::tag_name);
+        println!("Payload format: {}", payload_format);
+        println!("Payload coding: {}", payload_coding);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    index_repo::tokio::main(tokio_async_await::compat::backward::Compat::new(bootstrap()))
+}
@@ -18,7 +18,8 @@ async fn bootstrap() -> Result<(), Error> {
     let path = matches.value_of("RPM").unwrap();
     let in_file = await_old!(tokio::fs::File::open(path.to_owned()))?;
     let mut out_file = await_old!(tokio::fs::File::create(path.to_owned() + ".cpio"))?;
-    let (mut a, _pos, _lead, _signature_header, _header) = await!(rpm::read_all_headers(in_file))?;
+    let (mut a, _pos, _lead, _signature_header, _header, verifier) =
+        await!(rpm::read_all_headers(in_file))?;
     let mut buf = vec![0u8; 8192];
     loop {
         let (local_a, local_buf, n) = await_old!(read(a, buf))?;
@@ -32,6 +33,7 @@ async fn bootstrap() -> Result<(), Error> {
         buf = local_window.into_inner();
         out_file = local_out_file;
     }
+    verifier.verify_payload()?;
     Ok(())
 }
 
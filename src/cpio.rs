@@ -1,13 +1,20 @@
+use std::cell::RefCell;
 use std::cmp::min;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
 use std::str::from_utf8;
 use std::u64;
 
-use failure::{Error, format_err, ResultExt};
-use nom::{apply, do_parse, error_position, named, tag, take};
+use arrayref::array_ref;
+use failure::{bail, Error, format_err, ResultExt};
+use nom::{alt, apply, do_parse, error_position, named, tag, take};
 use tokio_io::AsyncRead;
-use tokio_io::io::{read_exact, Window};
+use tokio_io::io::{read_exact, read_to_end, Window};
 
 use crate::errors::FutureExt;
+use crate::limits::{advance, check_limit, limits, padding};
+use crate::rpm::{FileInfo, S_IFMT, S_IFREG};
 
 fn parse_u64(i: &[u8], n: usize) -> nom::IResult<&[u8], u64> {
     do_parse!(i, b: take!(n) >> (b))
@@ -35,13 +42,21 @@ pub struct Header {
     pub c_checksum: u64,
 }
 
-static HEADER_MAGIC: [u8; 6] = [0x30, 0x37, 0x30, 0x37, 0x30, 0x31];
+static NEWC_MAGIC: &[u8] = b"070701";
+
+static CRC_MAGIC: &[u8] = b"070702";
+
+static STRIPPED_MAGIC: &[u8] = b"07070X";
+
+static MAGIC_SIZE: usize = 6;
 
 static HEADER_SIZE: usize = 110;
 
-named!(parse_header<Header>,
+static STRIPPED_HEADER_SIZE: usize = 14;
+
+named!(pub parse_header<Header>,
     do_parse!(
-        tag!(HEADER_MAGIC) >>
+        c_magic: alt!(tag!(NEWC_MAGIC) | tag!(CRC_MAGIC)) >>
         c_ino: apply!(parse_u64, 8) >>
         c_mode: apply!(parse_u64, 8) >>
         c_uid: apply!(parse_u64, 8) >>
@@ -56,7 +71,7 @@ named!(parse_header<Header>,
         c_namesize: apply!(parse_u64, 8) >>
         c_checksum: apply!(parse_u64, 8) >>
         (Header {
-            c_magic: HEADER_MAGIC,
+            c_magic: *array_ref![c_magic, 0, 6],
             c_ino,
             c_mode,
             c_uid,
@@ -73,27 +88,89 @@ named!(parse_header<Header>,
         }))
 );
 
+// rpm's stripped cpio variant, which it uses for packages with files over
+// 4 GiB, keeps only an index into the RPM header's file list. The header is
+// padded to 4 bytes, like the file name in the other variants.
+named!(parse_stripped_header<u64>,
+    do_parse!(
+        tag!(STRIPPED_MAGIC) >>
+        fx: apply!(parse_u64, 8) >>
+        (fx))
+);
+
+pub enum RawHeader {
+    Newc(Header),
+    Stripped(u64),
+}
+
+fn raw_header_size(magic: &[u8]) -> usize {
+    if magic == STRIPPED_MAGIC {
+        STRIPPED_HEADER_SIZE
+    } else {
+        HEADER_SIZE
+    }
+}
+
+fn parse_raw_header(buf: &[u8]) -> Result<RawHeader, Error> {
+    if buf.len() == STRIPPED_HEADER_SIZE {
+        let (_, fx) = parse_stripped_header(buf)
+            .map_err(|_| format_err!("Could not parse stripped CPIO header"))?;
+        Ok(RawHeader::Stripped(fx))
+    } else {
+        let (_, header) = parse_header(buf)
+            .map_err(|_| format_err!("Could not parse CPIO header - bad magic?"))?;
+        Ok(RawHeader::Newc(header))
+    }
+}
+
 pub async fn read_header<A: AsyncRead + Send + 'static>(
     a: A, pos: usize,
-) -> Result<(A, usize, Header), Error> {
-    let (a, buf) = await_old!(read_exact(a, vec![0u8; HEADER_SIZE])
+) -> Result<(A, usize, RawHeader), Error> {
+    let (a, mut buf) = await_old!(read_exact(a, vec![0u8; MAGIC_SIZE])
+        .context("Could not read CPIO header"))?;
+    let size = raw_header_size(&buf);
+    buf.resize(size, 0);
+    let mut window = Window::new(buf);
+    window.set_start(MAGIC_SIZE);
+    let (a, window) = await_old!(read_exact(a, window)
         .context("Could not read CPIO header"))?;
-    let (_, header) = parse_header(&buf)
-        .map_err(|_| format_err!("Could not parse CPIO header - bad magic?"))?;
-    Ok((a, pos + HEADER_SIZE, header))
+    let header = parse_raw_header(&window.into_inner())?;
+    Ok((a, advance(pos, size)?, header))
+}
+
+fn name_padding(pos: usize, size: usize) -> Result<usize, Error> {
+    check_limit("CPIO file name size", size as u64, limits().max_name_size)?;
+    Ok(padding(advance(pos, size)?, 4))
+}
+
+fn parse_name(name: &[u8], size: usize) -> Result<String, Error> {
+    if size == 0 || size > name.len() {
+        bail!("Malformed CPIO file name size: {}", size);
+    }
+    let s = from_utf8(&name[..size - 1])
+        .context("Malformed CPIO file name")?
+        .to_owned();
+    Ok(s)
 }
 
 pub async fn read_name<A: AsyncRead + Send + 'static>(
     a: A, pos: usize, size: usize,
 ) -> Result<(A, usize, String), Error> {
-    let end = pos + size;
-    let padding = ((end + 3) & !3) - end;
-    let (a, name) = await_old!(read_exact(a, vec![0u8; size + padding])
+    let len = advance(size, name_padding(pos, size)?)?;
+    let (a, name) = await_old!(read_exact(a, vec![0u8; len])
         .context("Could not read CPIO file name"))?;
-    let s = from_utf8(&name[..size - 1])
-        .context("Malformed CPIO file name")?
-        .to_owned();
-    Ok((a, pos + size + padding, s))
+    let s = parse_name(&name, size)?;
+    Ok((a, advance(pos, len)?, s))
+}
+
+/// Converts an archive name, e.g. "./usr/bin/true", to an absolute path, the
+/// way it is spelled in RPM headers.
+pub fn entry_path(name: &str) -> String {
+    if name.starts_with('/') {
+        name.to_owned()
+    } else {
+        "/".to_owned() + name.trim_start_matches("./")
+    }
 }
 
 pub struct Entry {
@@ -102,58 +179,332 @@ pub struct Entry {
     pub peek: Window<Vec<u8>>,
 }
 
-pub async fn read_entry_start<A: AsyncRead + Send + 'static>(
-    a: A, pos: usize,
+fn make_stripped_entry(files: &[FileInfo], fx: u64) -> Result<(Header, String), Error> {
+    let file = usize::try_from(fx).ok()
+        .and_then(|fx| files.get(fx))
+        .ok_or_else(|| format_err!("Stripped CPIO file index {} is out of bounds", fx))?;
+    let header = Header {
+        c_magic: *array_ref![STRIPPED_MAGIC, 0, 6],
+        c_ino: u64::from(file.inode),
+        c_mode: u64::from(file.mode),
+        c_uid: 0,
+        c_gid: 0,
+        c_nlink: u64::from(file.nlink),
+        c_mtime: u64::from(file.mtime),
+        c_filesize: if file.has_content { file.size } else { 0 },
+        c_devmajor: u64::from(file.device),
+        c_devminor: 0,
+        c_rdevmajor: 0,
+        c_rdevminor: 0,
+        c_namesize: 0,
+        c_checksum: 0,
+    };
+    Ok((header, ".".to_owned() + &file.path))
+}
+
+/// `files` is the file list from the RPM header, which is needed to make
+/// sense of stripped CPIO entries.
+pub async fn read_entry_start<'a, A: AsyncRead + Send + 'static>(
+    a: A, pos: usize, files: &'a [FileInfo],
 ) -> Result<(A, usize, Option<Entry>), Error> {
     let (a, pos, header) = await!(read_header(a, pos))?;
-    let c_namesize = header.c_namesize as usize;
-    let (a, pos, name) = await!(read_name(a, pos, c_namesize))?;
-    if name == "TRAILER!!!" {
-        return Ok((a, pos, None));
-    }
-    let size = min(header.c_filesize as usize, 8192);
+    let (a, pos, header, name) = match header {
+        RawHeader::Newc(header) => {
+            let c_namesize = usize::try_from(header.c_namesize)?;
+            let (a, pos, name) = await!(read_name(a, pos, c_namesize))?;
+            if name == "TRAILER!!!" {
+                return Ok((a, pos, None));
+            }
+            (a, pos, header, name)
+        }
+        RawHeader::Stripped(fx) => {
+            let padding = padding(pos, 4);
+            let (a, _) = await_old!(read_exact(a, vec![0u8; padding])
+                .context("Could not read CPIO header padding"))?;
+            let (header, name) = make_stripped_entry(files, fx)?;
+            (a, advance(pos, padding)?, header, name)
+        }
+    };
+    let size = min(header.c_filesize, 8192) as usize;
     let (a, peek) = await_old!(read_exact(a, Window::new(vec![0u8; size])))?;
-    Ok((a, pos + size, Some(Entry {
+    Ok((a, advance(pos, size)?, Some(Entry {
         header,
         name,
         peek,
     })))
 }
 
-pub async fn read_entry_data<A: AsyncRead + Send + 'static>(
-    a: A, pos: usize, c_filesize: u64, peek: Window<Vec<u8>>,
+/// Groups the names of hardlinked files. rpm stores the data only with the
+/// last link, the other links have c_filesize == 0.
+#[derive(Default)]
+pub struct Hardlinks {
+    pending: HashMap<(u64, u64, u64), Vec<String>>,
+}
+
+impl Hardlinks {
+    /// Returns all names of the file if `entry` holds its data. For the
+    /// other links, returns an empty list and remembers the name.
+    pub fn add(&mut self, entry: &Entry) -> Vec<String> {
+        let header = &entry.header;
+        if header.c_nlink <= 1 || header.c_mode & u64::from(S_IFMT) != u64::from(S_IFREG) {
+            return vec![entry.name.clone()];
+        }
+        let key = (header.c_ino, header.c_devmajor, header.c_devminor);
+        let names = self.pending.entry(key).or_default();
+        names.push(entry.name.clone());
+        if header.c_filesize == 0 {
+            Vec::new()
+        } else {
+            self.pending.remove(&key).unwrap_or_default()
+        }
+    }
+}
+
+/// The "CRC" of the 070702 variant, which is just the sum of the data bytes.
+fn checksum(sum: u32, data: &[u8]) -> u32 {
+    data.iter().fold(sum, |sum, b| sum.wrapping_add(u32::from(*b)))
+}
+
+fn verify_checksum(header: &Header, sum: u32) -> Result<(), Error> {
+    if &header.c_magic[..] == CRC_MAGIC && u64::from(sum) != header.c_checksum {
+        bail!("CPIO entry checksum {} does not match the data checksum {}",
+              header.c_checksum, sum);
+    }
+    Ok(())
+}
+
+/// The part of the entry data that follows `peek`.
+fn remaining_data(c_filesize: u64, peek: &Window<Vec<u8>>) -> Result<u64, Error> {
+    c_filesize.checked_sub((peek.end() - peek.start()) as u64)
+        .ok_or_else(|| format_err!("CPIO entry data is shorter than its peek"))
+}
+
+/// Reads the entry data into memory and verifies its checksum.
+pub async fn read_entry_data<'a, A: AsyncRead + Send + 'static>(
+    a: A, pos: usize, header: &'a Header, peek: Window<Vec<u8>>,
 ) -> Result<(A, usize, Vec<u8>), Error> {
-    let c_filesize = c_filesize as usize;
-    let mut data = vec![0u8; c_filesize];
-    let peek_len = peek.end() - peek.start();
-    data[..peek_len].copy_from_slice(peek.as_ref());
-    let mut window = Window::new(data);
-    window.set_start(peek_len);
-    let (a, window) = await_old!(read_exact(a, window))?;
-    Ok((a, pos + (c_filesize - peek_len), window.into_inner()))
+    let c_filesize = header.c_filesize;
+    check_limit("CPIO entry size", c_filesize, limits().max_entry_data_size)?;
+    let remaining = remaining_data(c_filesize, &peek)?;
+    // Grow the buffer as the data arrives rather than trusting c_filesize
+    let (a, data) = await_old!(read_to_end(a.take(remaining), peek.as_ref().to_vec())
+        .context("Could not read CPIO entry data"))?;
+    if data.len() as u64 != c_filesize {
+        bail!("Unexpected end of CPIO entry data");
+    }
+    verify_checksum(header, checksum(0, &data))?;
+    Ok((a.into_inner(), advance(pos, usize::try_from(remaining)?)?, data))
 }
 
 pub async fn skip_entry_data<A: AsyncRead + Send + 'static>(
     mut a: A, mut pos: usize, c_filesize: u64, mut peek: Window<Vec<u8>>,
 ) -> Result<(A, usize), Error> {
-    let mut remaining = c_filesize as usize - (peek.end() - peek.start());
+    let mut remaining = remaining_data(c_filesize, &peek)?;
     while remaining > 0 {
-        if remaining < peek.end() {
-            peek.set_end(remaining);
+        if remaining < peek.end() as u64 {
+            peek.set_end(remaining as usize);
         }
         let (local_a, local_peek, n) = await_old!(tokio_io::io::read(a, peek))?;
-        remaining -= n;
-        pos += n;
+        if n == 0 {
+            bail!("Unexpected end of CPIO entry data");
+        }
+        remaining -= n as u64;
+        pos = advance(pos, n)?;
         a = local_a;
         peek = local_peek;
     }
     Ok((a, pos))
 }
 
+/// Writes the entry data to `w`, without holding all of it in memory, and
+/// verifies its checksum.
+pub async fn copy_entry_data<'a, A: AsyncRead + Send + 'static, W: Write>(
+    mut a: A, mut pos: usize, header: &'a Header, mut peek: Window<Vec<u8>>, mut w: W,
+) -> Result<(A, usize, W), Error> {
+    w.write_all(peek.as_ref()).context("Could not write CPIO entry data")?;
+    let mut sum = checksum(0, peek.as_ref());
+    let mut remaining = remaining_data(header.c_filesize, &peek)?;
+    while remaining > 0 {
+        peek.set_start(0);
+        peek.set_end(min(remaining, peek.get_ref().len() as u64) as usize);
+        let (local_a, local_peek, n) = await_old!(tokio_io::io::read(a, peek))?;
+        if n == 0 {
+            bail!("Unexpected end of CPIO entry data");
+        }
+        w.write_all(&local_peek.get_ref()[..n]).context("Could not write CPIO entry data")?;
+        sum = checksum(sum, &local_peek.get_ref()[..n]);
+        remaining -= n as u64;
+        pos = advance(pos, n)?;
+        a = local_a;
+        peek = local_peek;
+    }
+    verify_checksum(header, sum)?;
+    Ok((a, pos, w))
+}
+
 pub async fn read_entry_end<A: AsyncRead + Send + 'static>(
     a: A, pos: usize,
 ) -> Result<(A, usize), Error> {
-    let padding = ((pos + 3) & !3) - pos;
+    let padding = padding(pos, 4);
     let (a, _) = await_old!(tokio_io::io::read_exact(a, vec![0u8; padding]))?;
-    Ok((a, pos + padding))
+    Ok((a, advance(pos, padding)?))
+}
+
+struct ArchiveState<R> {
+    inner: R,
+    pos: usize,
+    /// Number of the current entry.
+    index: usize,
+    /// Unread data of the current entry.
+    remaining: u64,
+    /// Checksum of the data of the current entry read so far.
+    sum: u32,
+    done: bool,
+}
+
+impl<R: Read> ArchiveState<R> {
+    fn read_exact(&mut self, size: usize, what: &str) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0u8; size];
+        self.inner
+            .read_exact(&mut buf)
+            .with_context(|_| format!("Could not read CPIO {}", what))?;
+        self.pos = advance(self.pos, size)?;
+        Ok(buf)
+    }
+
+    fn skip_rest(&mut self) -> Result<(), Error> {
+        let n = io::copy(&mut (&mut self.inner).take(self.remaining), &mut io::sink())
+            .context("Could not read CPIO entry data")?;
+        if n != self.remaining {
+            bail!("Unexpected end of CPIO entry data");
+        }
+        self.pos = advance(self.pos, usize::try_from(n)?)?;
+        self.remaining = 0;
+        let padding = padding(self.pos, 4);
+        self.read_exact(padding, "entry padding")?;
+        Ok(())
+    }
+
+    fn next_entry(&mut self, files: &[FileInfo]) -> Result<Option<(Header, String)>, Error> {
+        self.skip_rest()?;
+        let mut buf = self.read_exact(MAGIC_SIZE, "header")?;
+        buf.extend(self.read_exact(raw_header_size(&buf) - MAGIC_SIZE, "header")?);
+        let (header, name) = match parse_raw_header(&buf)? {
+            RawHeader::Newc(header) => {
+                let size = usize::try_from(header.c_namesize)?;
+                let len = advance(size, name_padding(self.pos, size)?)?;
+                let name = parse_name(&self.read_exact(len, "file name")?, size)?;
+                if name == "TRAILER!!!" {
+                    return Ok(None);
+                }
+                (header, name)
+            }
+            RawHeader::Stripped(fx) => {
+                let padding = padding(self.pos, 4);
+                self.read_exact(padding, "header padding")?;
+                make_stripped_entry(files, fx)?
+            }
+        };
+        self.index += 1;
+        self.remaining = header.c_filesize;
+        self.sum = 0;
+        Ok(Some((header, name)))
+    }
+}
+
+/// Blocking counterpart of read_entry_start() and friends.
+pub struct Archive<R> {
+    state: RefCell<ArchiveState<R>>,
+    files: Vec<FileInfo>,
+}
+
+impl<R: Read> Archive<R> {
+    /// `files` is the file list from the RPM header, see read_entry_start().
+    pub fn new(inner: R, files: Vec<FileInfo>) -> Archive<R> {
+        Archive {
+            state: RefCell::new(ArchiveState {
+                inner,
+                pos: 0,
+                index: 0,
+                remaining: 0,
+                sum: 0,
+                done: false,
+            }),
+            files,
+        }
+    }
+
+    /// Entries must be read in order: advancing the iterator skips the rest
+    /// of the previous entry.
+    pub fn entries(&self) -> Entries<R> {
+        Entries { archive: self }
+    }
+
+    /// Returns the underlying reader, which is positioned after the trailer
+    /// if all entries were read.
+    pub fn into_inner(self) -> R {
+        self.state.into_inner().inner
+    }
+}
+
+pub struct Entries<'a, R> {
+    archive: &'a Archive<R>,
+}
+
+impl<'a, R: Read> Iterator for Entries<'a, R> {
+    type Item = Result<ArchiveEntry<'a, R>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = self.archive.state.borrow_mut();
+        if state.done {
+            return None;
+        }
+        match state.next_entry(&self.archive.files) {
+            Ok(Some((header, name))) => Some(Ok(ArchiveEntry {
+                header,
+                name,
+                index: state.index,
+                archive: self.archive,
+            })),
+            Ok(None) => {
+                state.done = true;
+                None
+            }
+            Err(e) => {
+                state.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Reads the data of an entry.
+pub struct ArchiveEntry<'a, R> {
+    pub header: Header,
+    pub name: String,
+    index: usize,
+    archive: &'a Archive<R>,
+}
+
+impl<'a, R: Read> Read for ArchiveEntry<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.archive.state.borrow_mut();
+        if state.index != self.index {
+            return Err(io::Error::new(io::ErrorKind::Other, "CPIO entry is no longer current"));
+        }
+        let size = min(buf.len() as u64, state.remaining) as usize;
+        let n = state.inner.read(&mut buf[..size])?;
+        if n == 0 && size != 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        state.remaining -= n as u64;
+        state.pos = state.pos.checked_add(n)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "CPIO archive is too large"))?;
+        state.sum = checksum(state.sum, &buf[..n]);
+        if state.remaining == 0 {
+            verify_checksum(&self.header, state.sum)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+        Ok(n)
+    }
 }
@@ -3,6 +3,7 @@ use std::iter::FromIterator;
 use std::path::Path;
 
 use diesel::dsl::exists;
+use diesel::expression::AsExpression;
 use diesel::prelude::*;
 use diesel::query_source::joins::{Inner, Join};
 use diesel::sql_types;
@@ -10,9 +11,12 @@ use failure::{bail, Error, format_err, ResultExt};
 use itertools::Itertools;
 use smallvec::SmallVec;
 
+use crate::elf::{DynamicEntry, Properties, Symbol};
 use crate::metrics::{timed, timed_result, update_metrics};
 use crate::models::*;
 use crate::repomd;
+use crate::rpm::{Dependency, FileInfo, PackageMetadata, SourceFile};
+use crate::signatures::SignatureCheck;
 use crate::schema::*;
 
 fn like_from_wildcard(s: &str) -> String {
@@ -30,6 +34,18 @@ fn like_from_wildcard(s: &str) -> String {
     }).collect()
 }
 
+diesel_infix_operator!(Glob, " GLOB ");
+
+/// SQLite GLOB, which unlike LIKE is case-sensitive and can use an index on `left` as long
+/// as `pattern` does not start with a wildcard.
+pub fn glob<T, U>(left: T, pattern: U) -> Glob<T, U::Expression>
+where
+    T: Expression<SqlType = sql_types::Text>,
+    U: AsExpression<sql_types::Text>,
+{
+    Glob::new(left, pattern.as_expression())
+}
+
 pub fn get_packages(
     path: &Path,
     arches: &Option<Vec<String>>,
@@ -94,6 +110,17 @@ macro_rules! insert_into_returning_rowid {
     }}
 }
 
+no_arg_sql_function!(last_insert_rowid, sql_types::Integer);
+
+// Unlike insert_into_returning_rowid!, works for rows with NULL values.
+// The caller must hold the connection until the rowid is queried.
+fn query_last_insert_rowid(conn: &SqliteConnection) -> Result<i32, Error> {
+    diesel::select(last_insert_rowid)
+        .get_result::<i32>(conn)
+        .context("Failed to query last_insert_rowid()")
+        .map_err(Error::from)
+}
+
 pub fn persist_repo(
     conn: &SqliteConnection,
     repo_uri: &str,
@@ -114,20 +141,36 @@ pub fn persist_package(
     conn: &SqliteConnection,
     repo_id: i32,
     p: &RpmPackage,
+    metadata: &PackageMetadata,
+    signature: &SignatureCheck,
 ) -> Result<i32, Error> {
-    let (package_id, t) = timed_result(|| insert_into_returning_rowid!(
-        conn,
-        packages::table,
-        packages::id,
-        "a package",
-        (
-            packages::repo_id.eq(repo_id),
-            packages::name.eq(&p.name),
-            packages::arch.eq(&p.arch),
-            packages::version.eq(&p.version),
-            packages::epoch.eq(&p.epoch),
-            packages::release.eq(&p.release),
-        )))?;
+    let (package_id, t) = timed_result(|| {
+        diesel::insert_into(packages::table)
+            .values((
+                packages::repo_id.eq(repo_id),
+                packages::name.eq(&p.name),
+                packages::arch.eq(&p.arch),
+                packages::version.eq(&p.version),
+                packages::epoch.eq(&p.epoch),
+                packages::release.eq(&p.release),
+                packages::summary.eq(&metadata.summary),
+                packages::description.eq(&metadata.description),
+                packages::license.eq(&metadata.license),
+                packages::url.eq(&metadata.url),
+                packages::vendor.eq(&metadata.vendor),
+                packages::packager.eq(&metadata.packager),
+                packages::buildhost.eq(&metadata.buildhost),
+                packages::buildtime.eq(&metadata.buildtime),
+                packages::sourcerpm.eq(&metadata.sourcerpm),
+                packages::package_group.eq(&metadata.group),
+                packages::installed_size.eq(&metadata.installed_size),
+                packages::signature_key_id.eq(&signature.key_id),
+                packages::signature_status.eq(signature.status.as_str()),
+            ))
+            .execute(conn)
+            .context("Failed to insert a package")?;
+        query_last_insert_rowid(conn)
+    })?;
     update_metrics(|metrics| {
         metrics.sql_packages_insert_count += 1;
         metrics.sql_packages_insert_time += t;
@@ -135,11 +178,53 @@ pub fn persist_package(
     Ok(package_id)
 }
 
+pub fn persist_file_manifest(
+    conn: &SqliteConnection,
+    package_id: i32,
+    manifest: &[FileInfo],
+) -> Result<(), Error> {
+    if manifest.is_empty() {
+        return Ok(());
+    }
+    let (_, t) = timed_result(|| diesel::insert_into(files::table)
+        .values(manifest
+            .iter()
+            .map(|f| (
+                files::package_id.eq(package_id),
+                files::name.eq(&f.path),
+                files::mode.eq(i32::from(f.mode)),
+                files::size.eq(f.size as i64),
+                files::user_name.eq(&f.user_name),
+                files::group_name.eq(&f.group_name),
+                files::mtime.eq(i64::from(f.mtime)),
+                files::digest.eq(&f.digest),
+                files::link_to.eq(&f.link_to),
+                files::flags.eq(f.flags as i32),
+            ))
+            .collect::<Vec<_>>())
+        .execute(conn)
+        .context("Failed to insert a file manifest"))?;
+    update_metrics(|metrics| {
+        metrics.sql_files_insert_count += manifest.len();
+        metrics.sql_files_insert_time += t;
+    })?;
+    Ok(())
+}
+
 pub fn persist_file(
     conn: &SqliteConnection,
     package_id: i32,
     name: &str,
 ) -> Result<i32, Error> {
+    let rows = files::table
+        .filter(files::package_id.eq(package_id).and(files::name.eq(name)))
+        .select(files::id)
+        .limit(1)
+        .load::<i32>(conn)
+        .context("Failed to query a file")?;
+    if let [file_id] = rows.as_slice() {
+        return Ok(*file_id);
+    }
     let (file_id, t) = timed_result(|| insert_into_returning_rowid!(
         conn,
         files::table,
@@ -210,29 +295,124 @@ fn persist_strings<'a>(
     Ok(mappings)
 }
 
+pub fn persist_elf_file(
+    conn: &SqliteConnection,
+    package_id: i32,
+    file_names: &[String],
+    properties: &Properties,
+) -> Result<(), Error> {
+    let values = file_names
+        .iter()
+        .map(|file_name| Ok((
+            elf_files::file_id.eq(persist_file(conn, package_id, file_name)?),
+            elf_files::machine.eq(&properties.machine),
+            elf_files::class.eq(properties.class),
+            elf_files::endianness.eq(properties.endianness),
+            elf_files::elf_type.eq(&properties.tpe),
+            elf_files::os_abi.eq(&properties.os_abi),
+            elf_files::interpreter.eq(properties.interpreter),
+            elf_files::pie.eq(properties.pie),
+        )))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let count = values.len();
+    let (_, t) = timed_result(|| diesel::insert_into(elf_files::table)
+        .values(values)
+        .execute(conn)
+        .context("Failed to insert ELF files"))?;
+    update_metrics(|metrics| {
+        metrics.sql_elf_files_insert_count += count;
+        metrics.sql_elf_files_insert_time += t;
+    })?;
+    Ok(())
+}
+
+pub fn persist_build_id(
+    conn: &SqliteConnection,
+    package_id: i32,
+    file_names: &[String],
+    build_id: &str,
+) -> Result<(), Error> {
+    let file_ids = file_names
+        .iter()
+        .map(|file_name| persist_file(conn, package_id, file_name))
+        .collect::<Result<Vec<_>, Error>>()?;
+    diesel::update(files::table.filter(files::id.eq_any(file_ids)))
+        .set(files::build_id.eq(build_id))
+        .execute(conn)
+        .context("Failed to update a build id")?;
+    Ok(())
+}
+
+/// Demangled names belong to strings, so they are shared by all symbols with the
+/// same name, and the existing ones are left alone.
+fn persist_demangled_names(
+    conn: &SqliteConnection,
+    symbols: &[Symbol],
+    mappings: &HashMap<&str, i32>,
+) -> Result<(), Error> {
+    let demangled_names: HashMap<i32, (&str, &str)> = HashMap::from_iter(symbols
+        .iter()
+        .filter_map(|symbol| Some((
+            *mappings.get(symbol.name)?,
+            (symbol.demangled.as_ref()?.as_str(), symbol.qualified_name.as_ref()?.as_str()),
+        ))));
+    if demangled_names.is_empty() {
+        return Ok(());
+    }
+    let count = demangled_names.len();
+    let (_, t) = timed_result(|| diesel::insert_or_ignore_into(demangled_names::table)
+        .values(demangled_names
+            .into_iter()
+            .map(|(name_id, (demangled, qualified_name))| (
+                demangled_names::name_id.eq(name_id),
+                demangled_names::demangled.eq(demangled),
+                demangled_names::qualified_name.eq(qualified_name),
+            ))
+            .collect::<Vec<_>>())
+        .execute(conn)
+        .context("Failed to insert demangled names"))?;
+    update_metrics(|metrics| {
+        metrics.sql_demangled_names_insert_count += count;
+        metrics.sql_demangled_names_insert_time += t;
+    })?;
+    Ok(())
+}
+
 pub fn persist_elf_symbols(
     conn: &SqliteConnection,
     package_id: i32,
-    file_name: &str,
-    symbols: Vec<(&str, i32, i32)>,
+    file_names: &[String],
+    symbols: Vec<Symbol>,
 ) -> Result<(), Error> {
-    let file_id = persist_file(conn, package_id, file_name)?;
+    let file_ids = file_names
+        .iter()
+        .map(|file_name| persist_file(conn, package_id, file_name))
+        .collect::<Result<Vec<_>, Error>>()?;
     let (strings, t): (HashSet<&str>, _) = timed(|| HashSet::from_iter(symbols
         .iter()
-        .map(|x| x.0)));
+        .map(|x| x.name)));
     update_metrics(|metrics| {
         metrics.strings_hashing_time += t;
     })?;
     let mappings = persist_strings(conn, strings)?;
-    let (symbols_values, t) = timed_result(|| symbols
-        .into_iter()
-        .map(|(name, st_info, st_other)| {
-            match mappings.get(name) {
+    let (symbols_values, t) = timed_result(|| file_ids
+        .iter()
+        .cartesian_product(symbols.iter())
+        .map(|(file_id, symbol)| {
+            match mappings.get(symbol.name) {
                 Some(name_id) => Ok((
-                    elf_symbols::file_id.eq(file_id),
+                    elf_symbols::file_id.eq(*file_id),
                     elf_symbols::name_id.eq(*name_id),
-                    elf_symbols::st_info.eq(st_info),
-                    elf_symbols::st_other.eq(st_other),
+                    elf_symbols::st_info.eq(i32::from(symbol.st_info)),
+                    elf_symbols::st_other.eq(i32::from(symbol.st_other)),
+                    elf_symbols::source.eq(symbol.source),
+                    elf_symbols::version.eq(symbol.version),
+                    elf_symbols::version_default.eq(symbol.version_default),
+                    elf_symbols::version_hidden.eq(symbol.version_hidden),
+                    elf_symbols::st_shndx.eq(i32::from(symbol.st_shndx)),
+                    elf_symbols::st_value.eq(symbol.st_value as i64),
+                    elf_symbols::st_size.eq(symbol.st_size as i64),
+                    elf_symbols::defined.eq(symbol.defined),
                 )),
                 None => Err(format_err!("persist_strings() has returned an unknown string")),
             }
@@ -241,6 +421,7 @@ pub fn persist_elf_symbols(
     update_metrics(|metrics| {
         metrics.symbols_mapping_time += t;
     })?;
+    persist_demangled_names(conn, &symbols, &mappings)?;
     let count = symbols_values.len();
     let (_, t) = timed_result(|| diesel::insert_into(elf_symbols::table)
         .values(symbols_values)
@@ -252,3 +433,154 @@ pub fn persist_elf_symbols(
     })?;
     Ok(())
 }
+
+pub fn persist_elf_dynamic_entries(
+    conn: &SqliteConnection,
+    package_id: i32,
+    file_names: &[String],
+    entries: &[DynamicEntry],
+) -> Result<(), Error> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let file_ids = file_names
+        .iter()
+        .map(|file_name| persist_file(conn, package_id, file_name))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let strings: HashSet<&str> = HashSet::from_iter(entries
+        .iter()
+        .map(|e| e.value));
+    let mappings = persist_strings(conn, strings)?;
+    let values = file_ids
+        .iter()
+        .cartesian_product(entries.iter())
+        .map(|(file_id, e)| match mappings.get(e.value) {
+            Some(value_id) => Ok((
+                elf_dynamic_entries::file_id.eq(*file_id),
+                elf_dynamic_entries::kind.eq(e.kind),
+                elf_dynamic_entries::value_id.eq(*value_id),
+            )),
+            None => Err(format_err!("persist_strings() has returned an unknown string")),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let count = values.len();
+    let (_, t) = timed_result(|| diesel::insert_into(elf_dynamic_entries::table)
+        .values(values)
+        .execute(conn)
+        .context("Failed to insert ELF dynamic entries"))?;
+    update_metrics(|metrics| {
+        metrics.sql_dynamic_entries_insert_count += count;
+        metrics.sql_dynamic_entries_insert_time += t;
+    })?;
+    Ok(())
+}
+
+pub fn persist_dependencies(
+    conn: &SqliteConnection,
+    package_id: i32,
+    dependencies: &[Dependency],
+) -> Result<(), Error> {
+    if dependencies.is_empty() {
+        return Ok(());
+    }
+    let strings: HashSet<&str> = HashSet::from_iter(dependencies
+        .iter()
+        .map(|d| d.name.as_str()));
+    let mappings = persist_strings(conn, strings)?;
+    let values = dependencies
+        .iter()
+        .map(|d| match mappings.get(d.name.as_str()) {
+            Some(name_id) => Ok((
+                dependencies::package_id.eq(package_id),
+                dependencies::kind.eq(d.kind),
+                dependencies::name_id.eq(*name_id),
+                dependencies::flags.eq(d.flags as i32),
+                dependencies::version.eq(&d.version),
+            )),
+            None => Err(format_err!("persist_strings() has returned an unknown string")),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let (_, t) = timed_result(|| diesel::insert_into(dependencies::table)
+        .values(values)
+        .execute(conn)
+        .context("Failed to insert dependencies"))?;
+    update_metrics(|metrics| {
+        metrics.sql_dependencies_insert_count += dependencies.len();
+        metrics.sql_dependencies_insert_time += t;
+    })?;
+    Ok(())
+}
+
+pub fn persist_source_files(
+    conn: &SqliteConnection,
+    package_id: i32,
+    files: &[SourceFile],
+) -> Result<(), Error> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    diesel::insert_into(source_files::table)
+        .values(files
+            .iter()
+            .map(|f| (
+                source_files::package_id.eq(package_id),
+                source_files::kind.eq(f.kind),
+                source_files::name.eq(&f.name),
+                source_files::size.eq(f.size.map(|size| size as i64)),
+                source_files::digest.eq(&f.digest),
+            ))
+            .collect::<Vec<_>>())
+        .execute(conn)
+        .context("Failed to insert source files")?;
+    Ok(())
+}
+
+pub fn persist_spec_file(
+    conn: &SqliteConnection,
+    package_id: i32,
+    name: &str,
+    content: &str,
+) -> Result<(), Error> {
+    diesel::insert_into(spec_files::table)
+        .values((
+            spec_files::package_id.eq(package_id),
+            spec_files::name.eq(name),
+            spec_files::content.eq(content),
+        ))
+        .execute(conn)
+        .context("Failed to insert a spec file")?;
+    Ok(())
+}
+
+/// Removes a package together with everything that was indexed for it.
+pub fn delete_package(conn: &SqliteConnection, package_id: i32) -> Result<(), Error> {
+    let package_files = files::table
+        .filter(files::package_id.eq(package_id))
+        .select(files::id);
+    diesel::delete(elf_symbols::table.filter(elf_symbols::file_id.eq_any(package_files)))
+        .execute(conn)
+        .context("Failed to delete ELF symbols")?;
+    diesel::delete(elf_dynamic_entries::table
+        .filter(elf_dynamic_entries::file_id.eq_any(package_files)))
+        .execute(conn)
+        .context("Failed to delete ELF dynamic entries")?;
+    diesel::delete(elf_files::table.filter(elf_files::file_id.eq_any(package_files)))
+        .execute(conn)
+        .context("Failed to delete ELF files")?;
+    diesel::delete(files::table.filter(files::package_id.eq(package_id)))
+        .execute(conn)
+        .context("Failed to delete files")?;
+    diesel::delete(dependencies::table.filter(dependencies::package_id.eq(package_id)))
+        .execute(conn)
+        .context("Failed to delete dependencies")?;
+    diesel::delete(source_files::table.filter(source_files::package_id.eq(package_id)))
+        .execute(conn)
+        .context("Failed to delete source files")?;
+    diesel::delete(spec_files::table.filter(spec_files::package_id.eq(package_id)))
+        .execute(conn)
+        .context("Failed to delete spec files")?;
+    diesel::delete(packages::table.filter(packages::id.eq(package_id)))
+        .execute(conn)
+        .context("Failed to delete a package")?;
+    Ok(())
+}
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+
+use cpp_demangle::DemangleOptions;
+use failure::{bail, Error, format_err};
+use goblin::elf::Elf;
+use goblin::elf::header::{ET_DYN, et_to_str, machine_to_str};
+use goblin::elf::note::NT_GNU_BUILD_ID;
+use goblin::elf::program_header::PT_NOTE;
+use goblin::elf::r#dyn::{DT_FLAGS_1, DT_NEEDED, DT_RPATH, DT_RUNPATH, DT_SONAME};
+use goblin::elf::section_header::{
+    SectionHeader, SHN_UNDEF, SHT_GNU_VERDEF, SHT_GNU_VERNEED, SHT_GNU_VERSYM, SHT_NOTE,
+};
+use goblin::elf::sym::Symtab;
+use goblin::strtab::Strtab;
+use log::warn;
+use scroll::{Endian, Pread};
+
+use crate::limits::{advance, padding};
+
+pub const SOURCE_DYNSYM: &str = "dynsym";
+pub const SOURCE_SYMTAB: &str = "symtab";
+
+pub const DYNAMIC_NEEDED: &str = "needed";
+pub const DYNAMIC_SONAME: &str = "soname";
+pub const DYNAMIC_RPATH: &str = "rpath";
+pub const DYNAMIC_RUNPATH: &str = "runpath";
+
+pub const ENDIANNESS_LITTLE: &str = "little";
+pub const ENDIANNESS_BIG: &str = "big";
+
+/// DT_FLAGS_1 flag that newer linkers set on position-independent executables.
+const DF_1_PIE: u64 = 0x0800_0000;
+
+const EI_OSABI: usize = 7;
+
+static OS_ABI_NAMES: &[(u8, &str)] = &[
+    (0, "SYSV"),
+    (1, "HPUX"),
+    (2, "NETBSD"),
+    (3, "GNU"),
+    (6, "SOLARIS"),
+    (7, "AIX"),
+    (8, "IRIX"),
+    (9, "FREEBSD"),
+    (10, "TRU64"),
+    (11, "MODESTO"),
+    (12, "OPENBSD"),
+    (64, "ARM_AEABI"),
+    (97, "ARM"),
+    (255, "STANDALONE"),
+];
+
+/// .gnu.version bit that makes a symbol invisible to `name` lookups without a version.
+const VERSYM_HIDDEN: u16 = 0x8000;
+/// .gnu.version_d flag of the entry that names the file itself rather than a version.
+const VER_FLG_BASE: u16 = 0x1;
+
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    /// `name` demangled as a Rust or an Itanium C++ symbol.
+    pub demangled: Option<String>,
+    /// `qualified_name()` of `name`.
+    pub qualified_name: Option<String>,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+    /// The file provides the symbol rather than imports it, i.e. st_shndx is not SHN_UNDEF.
+    pub defined: bool,
+    /// Which table the symbol comes from: SOURCE_DYNSYM or SOURCE_SYMTAB.
+    pub source: &'static str,
+    /// GNU symbol version, e.g. GLIBC_2.14. Only .dynsym symbols have one.
+    pub version: Option<&'a str>,
+    /// The symbol defines the default version, i.e. `name@@version`.
+    pub version_default: bool,
+    pub version_hidden: bool,
+}
+
+/// A string entry of the dynamic section.
+pub struct DynamicEntry<'a> {
+    /// One of the DYNAMIC_* constants.
+    pub kind: &'static str,
+    /// Library name, soname or search path list, as is.
+    pub value: &'a str,
+}
+
+/// What the ELF header and the program headers say about the file as a whole.
+pub struct Properties<'a> {
+    /// e_machine, e.g. X86_64 or BPF, or its number if it is not known.
+    pub machine: String,
+    /// 32 or 64.
+    pub class: i32,
+    /// ENDIANNESS_LITTLE or ENDIANNESS_BIG.
+    pub endianness: &'static str,
+    /// e_type, e.g. EXEC, DYN, REL or CORE.
+    pub tpe: String,
+    /// e_ident[EI_OSABI], e.g. SYSV or GNU, or its number if it is not known.
+    pub os_abi: String,
+    /// PT_INTERP.
+    pub interpreter: Option<&'a str>,
+    pub pie: bool,
+}
+
+struct Version<'a> {
+    name: &'a str,
+    /// Comes from .gnu.version_d rather than from .gnu.version_r.
+    defined: bool,
+}
+
+/// Maps .dynsym indices to GNU symbol versions.
+struct Versions<'a> {
+    endian: Endian,
+    versym: &'a [u8],
+    names: HashMap<u16, Version<'a>>,
+}
+
+impl<'a> Versions<'a> {
+    /// Returns the version, whether it is the default one and whether it is hidden.
+    fn get(&self, index: usize) -> (Option<&'a str>, bool, bool) {
+        let versym = match index.checked_mul(2) {
+            Some(offset) => self.versym.pread_with::<u16>(offset, self.endian).unwrap_or(0),
+            None => 0,
+        };
+        let hidden = versym & VERSYM_HIDDEN != 0;
+        match self.names.get(&(versym & !VERSYM_HIDDEN)) {
+            Some(version) => (Some(version.name), version.defined && !hidden, hidden),
+            None => (None, false, hidden),
+        }
+    }
+}
+
+fn file_data(bytes: &[u8], offset: u64, size: u64) -> Result<&[u8], Error> {
+    let start = offset as usize;
+    let end = advance(start, size as usize)?;
+    bytes.get(start..end).ok_or_else(|| format_err!("Range {}..{} is out of bounds", start, end))
+}
+
+fn section_data<'a>(bytes: &'a [u8], shdr: &SectionHeader) -> Result<&'a [u8], Error> {
+    file_data(bytes, shdr.sh_offset, shdr.sh_size)
+}
+
+fn tail(data: &[u8], offset: usize) -> Result<&[u8], Error> {
+    data.get(offset..).ok_or_else(|| format_err!("Offset {} is out of bounds", offset))
+}
+
+fn version_name<'a>(strtab: &Strtab<'a>, offset: u32) -> Result<&'a str, Error> {
+    match strtab.get(offset as usize) {
+        Some(Ok(t)) => Ok(t),
+        _ => bail!("Malformed version name offset {}", offset),
+    }
+}
+
+/// Elf_Verdef entries followed by their Elf_Verdaux entries, the first of which names the
+/// version.
+fn parse_verdef<'a>(
+    data: &[u8], count: u32, strtab: &Strtab<'a>, endian: Endian,
+    names: &mut HashMap<u16, Version<'a>>,
+) -> Result<(), Error> {
+    let mut offset = 0;
+    for _ in 0..count {
+        let verdef = tail(data, offset)?;
+        let vd_flags: u16 = verdef.pread_with(2, endian)?;
+        let vd_ndx: u16 = verdef.pread_with(4, endian)?;
+        let vd_aux: u32 = verdef.pread_with(12, endian)?;
+        let vd_next: u32 = verdef.pread_with(16, endian)?;
+        if vd_flags & VER_FLG_BASE == 0 {
+            let vda_name: u32 = verdef.pread_with(vd_aux as usize, endian)?;
+            let name = version_name(strtab, vda_name)?;
+            names.insert(vd_ndx, Version { name, defined: true });
+        }
+        if vd_next == 0 {
+            break;
+        }
+        offset = advance(offset, vd_next as usize)?;
+    }
+    Ok(())
+}
+
+/// Elf_Verneed entries, one per needed library, followed by their Elf_Vernaux entries, one
+/// per version.
+fn parse_verneed<'a>(
+    data: &[u8], count: u32, strtab: &Strtab<'a>, endian: Endian,
+    names: &mut HashMap<u16, Version<'a>>,
+) -> Result<(), Error> {
+    let mut offset = 0;
+    for _ in 0..count {
+        let verneed = tail(data, offset)?;
+        let vn_cnt: u16 = verneed.pread_with(2, endian)?;
+        let vn_aux: u32 = verneed.pread_with(8, endian)?;
+        let vn_next: u32 = verneed.pread_with(12, endian)?;
+        let mut aux_offset = advance(offset, vn_aux as usize)?;
+        for _ in 0..vn_cnt {
+            let vernaux = tail(data, aux_offset)?;
+            let vna_other: u16 = vernaux.pread_with(6, endian)?;
+            let vna_name: u32 = vernaux.pread_with(8, endian)?;
+            let vna_next: u32 = vernaux.pread_with(12, endian)?;
+            let name = version_name(strtab, vna_name)?;
+            names.insert(vna_other, Version { name, defined: false });
+            if vna_next == 0 {
+                break;
+            }
+            aux_offset = advance(aux_offset, vna_next as usize)?;
+        }
+        if vn_next == 0 {
+            break;
+        }
+        offset = advance(offset, vn_next as usize)?;
+    }
+    Ok(())
+}
+
+/// Version names are looked up in .dynstr, which is where linkers put them.
+fn parse_versions<'a>(elf: &Elf<'a>, bytes: &'a [u8]) -> Result<Versions<'a>, Error> {
+    let endian = if elf.little_endian { Endian::Little } else { Endian::Big };
+    let mut versions = Versions { endian, versym: &[], names: HashMap::new() };
+    for shdr in &elf.section_headers {
+        match shdr.sh_type {
+            SHT_GNU_VERSYM => versions.versym = section_data(bytes, shdr)?,
+            SHT_GNU_VERDEF => parse_verdef(
+                section_data(bytes, shdr)?, shdr.sh_info, &elf.dynstrtab, endian,
+                &mut versions.names)?,
+            SHT_GNU_VERNEED => parse_verneed(
+                section_data(bytes, shdr)?, shdr.sh_info, &elf.dynstrtab, endian,
+                &mut versions.names)?,
+            _ => {}
+        }
+    }
+    Ok(versions)
+}
+
+/// Legacy Rust symbols are valid C++ symbols too, but they always end with a
+/// hash element, e.g. 17h0123456789abcdefE.
+pub fn is_rust_legacy(name: &str) -> bool {
+    let name = name.as_bytes();
+    if !name.starts_with(b"_ZN") || name.len() < 23 {
+        return false;
+    }
+    let hash = &name[name.len() - 20..];
+    hash.starts_with(b"17h") && hash.ends_with(b"E") &&
+        hash[3..19].iter().all(u8::is_ascii_hexdigit)
+}
+
+fn demangle_with(name: &str, options: &DemangleOptions) -> Option<String> {
+    if name.starts_with("_R") || is_rust_legacy(name) {
+        if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+            // The alternate form leaves out the hash
+            return Some(format!("{:#}", demangled));
+        }
+    }
+    if name.starts_with("_Z") {
+        let symbol = cpp_demangle::Symbol::new(name).ok()?;
+        return symbol.demangle(options).ok();
+    }
+    None
+}
+
+pub fn demangle(name: &str) -> Option<String> {
+    demangle_with(name, &DemangleOptions::default())
+}
+
+/// Rust symbols have no parameters to begin with.
+pub fn demangle_without_params(name: &str) -> Option<String> {
+    demangle_with(name, &DemangleOptions { no_params: true })
+}
+
+/// A demangled name without parameters, split into template argument lists
+/// and the text around them.
+#[derive(Debug, PartialEq)]
+enum NamePart<'a> {
+    Text(&'a str),
+    Args(Vec<Vec<NamePart<'a>>>),
+}
+
+fn push_text<'a>(parts: &mut Vec<NamePart<'a>>, text: &'a str) {
+    let text = text.trim();
+    if !text.is_empty() {
+        parts.push(NamePart::Text(text));
+    }
+}
+
+/// Whether the character at `pos` belongs to an operator name such as operator<,
+/// operator<<=, operator-> or operator, rather than to a template argument list.
+fn in_operator_name(name: &str, pos: usize) -> bool {
+    name[..pos].trim_end_matches(&['<', '>', '=', '-'][..]).ends_with("operator")
+}
+
+/// Stops before the , or > that ends a template argument when `nested`.
+/// Returns None if a template argument list is not closed.
+fn parse_name_parts<'a>(name: &'a str, pos: &mut usize, nested: bool) -> Option<Vec<NamePart<'a>>> {
+    let mut parts = Vec::new();
+    let mut start = *pos;
+    while let Some(c) = name[*pos..].chars().next() {
+        match c {
+            '<' if !in_operator_name(name, *pos) => {
+                push_text(&mut parts, &name[start..*pos]);
+                *pos += 1;
+                let mut args = Vec::new();
+                loop {
+                    args.push(parse_name_parts(name, pos, true)?);
+                    let end = *name.as_bytes().get(*pos)?;
+                    *pos += 1;
+                    if end == b'>' {
+                        break;
+                    }
+                }
+                parts.push(NamePart::Args(args));
+                start = *pos;
+            }
+            ',' | '>' if nested && !in_operator_name(name, *pos) => break,
+            _ => *pos += c.len_utf8(),
+        }
+    }
+    push_text(&mut parts, &name[start..*pos]);
+    Some(parts)
+}
+
+fn parse_name<'a>(name: &'a str) -> Option<Vec<NamePart<'a>>> {
+    parse_name_parts(name, &mut 0, false)
+}
+
+/// A demangled name without parameters and template arguments, e.g.
+/// std::vector::push_back for std::vector<int, std::allocator<int> >::push_back.
+pub fn qualified_name(name: &str) -> String {
+    match parse_name(name) {
+        Some(parts) => parts.iter()
+            .filter_map(|part| match part {
+                NamePart::Text(text) => Some(*text),
+                NamePart::Args(_) => None,
+            })
+            .collect(),
+        None => name.to_owned(),
+    }
+}
+
+fn name_parts_match(pattern: &[NamePart], name: &[NamePart]) -> bool {
+    pattern.len() == name.len() && pattern.iter().zip(name).all(|parts| match parts {
+        (NamePart::Text(pattern), NamePart::Text(name)) => pattern == name,
+        (NamePart::Args(pattern), NamePart::Args(name)) => pattern.len() <= name.len() &&
+            pattern.iter().zip(name).all(|(pattern, name)| name_parts_match(pattern, name)),
+        _ => false,
+    })
+}
+
+/// Whether `pattern` is `name`, a demangled name without parameters, with
+/// some trailing template arguments left out, the way default ones are in
+/// source code, e.g. std::vector<int>::push_back matches
+/// std::vector<int, std::allocator<int> >::push_back.
+pub fn template_args_match(pattern: &str, name: &str) -> bool {
+    match (parse_name(pattern), parse_name(name)) {
+        (Some(pattern), Some(name)) => name_parts_match(&pattern, &name),
+        _ => pattern == name,
+    }
+}
+
+fn table_symbols<'a>(
+    symtab: &Symtab<'a>, strtab: &Strtab<'a>, source: &'static str,
+    versions: Option<&Versions<'a>>, symbols: &mut Vec<Symbol<'a>>,
+) {
+    for (index, sym) in symtab.iter().enumerate() {
+        match strtab.get(sym.st_name) {
+            Some(Ok(name)) => {
+                let (version, version_default, version_hidden) = versions
+                    .map_or((None, false, false), |versions| versions.get(index));
+                symbols.push(Symbol {
+                    name,
+                    demangled: demangle(name),
+                    qualified_name: demangle_without_params(name)
+                        .map(|name| qualified_name(&name)),
+                    st_info: sym.st_info,
+                    st_other: sym.st_other,
+                    st_shndx: sym.st_shndx as u16,
+                    st_value: sym.st_value,
+                    st_size: sym.st_size,
+                    defined: sym.st_shndx != SHN_UNDEF as usize,
+                    source,
+                    version,
+                    version_default,
+                    version_hidden,
+                })
+            }
+            _ => warn!("Could not resolve an ELF symbol name"),
+        }
+    }
+}
+
+/// Symbols from both .dynsym and .symtab. Stripped files have no .symtab, so
+/// only their .dynsym symbols are returned. `bytes` is what `elf` was parsed
+/// from.
+pub fn symbols<'a>(elf: &Elf<'a>, bytes: &'a [u8]) -> Vec<Symbol<'a>> {
+    let versions = match parse_versions(elf, bytes) {
+        Ok(t) => Some(t),
+        Err(e) => {
+            warn!("Could not parse ELF symbol versions: {}", e);
+            None
+        }
+    };
+    let mut symbols = Vec::new();
+    table_symbols(&elf.dynsyms, &elf.dynstrtab, SOURCE_DYNSYM, versions.as_ref(), &mut symbols);
+    table_symbols(&elf.syms, &elf.strtab, SOURCE_SYMTAB, None, &mut symbols);
+    symbols
+}
+
+/// DT_NEEDED, DT_SONAME, DT_RPATH and DT_RUNPATH entries in the order in
+/// which they appear.
+pub fn dynamic_entries<'a>(elf: &Elf<'a>) -> Vec<DynamicEntry<'a>> {
+    let dynamic = match &elf.dynamic {
+        Some(t) => t,
+        None => return Vec::new(),
+    };
+    let mut entries = Vec::new();
+    for entry in &dynamic.dyns {
+        let kind = match entry.d_tag {
+            DT_NEEDED => DYNAMIC_NEEDED,
+            DT_SONAME => DYNAMIC_SONAME,
+            DT_RPATH => DYNAMIC_RPATH,
+            DT_RUNPATH => DYNAMIC_RUNPATH,
+            _ => continue,
+        };
+        match elf.dynstrtab.get(entry.d_val as usize) {
+            Some(Ok(value)) => entries.push(DynamicEntry { kind, value }),
+            _ => warn!("Could not resolve an ELF dynamic entry value"),
+        }
+    }
+    entries
+}
+
+fn known_or_number<T: ToString>(name: &str, unknown: &str, value: T) -> String {
+    if name == unknown {
+        value.to_string()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// A position-independent executable is an ET_DYN file that is either marked
+/// as such in DT_FLAGS_1, or, for older linkers, has an interpreter and no
+/// soname. Shared libraries such as libc.so.6 may have an interpreter too.
+fn is_pie(elf: &Elf) -> bool {
+    if elf.header.e_type != ET_DYN {
+        return false;
+    }
+    let flags_1 = elf.dynamic.iter()
+        .flat_map(|dynamic| dynamic.dyns.iter())
+        .filter(|entry| entry.d_tag == DT_FLAGS_1)
+        .fold(0, |flags_1, entry| flags_1 | entry.d_val);
+    flags_1 & DF_1_PIE != 0 || (elf.interpreter.is_some() && elf.soname.is_none())
+}
+
+pub fn properties<'a>(elf: &Elf<'a>) -> Properties<'a> {
+    let e_machine = elf.header.e_machine;
+    let e_type = elf.header.e_type;
+    let os_abi = elf.header.e_ident[EI_OSABI];
+    Properties {
+        machine: known_or_number(machine_to_str(e_machine), "EM_UNKNOWN", e_machine),
+        class: if elf.is_64 { 64 } else { 32 },
+        endianness: if elf.little_endian { ENDIANNESS_LITTLE } else { ENDIANNESS_BIG },
+        tpe: known_or_number(et_to_str(e_type), "UNKNOWN_ET", e_type),
+        os_abi: OS_ABI_NAMES.iter()
+            .find(|(value, _)| *value == os_abi)
+            .map_or_else(|| os_abi.to_string(), |(_, name)| (*name).to_owned()),
+        interpreter: elf.interpreter,
+        pie: is_pie(elf),
+    }
+}
+
+/// Looks for NT_GNU_BUILD_ID among Elf_Nhdr entries, each followed by its
+/// name and its descriptor, both padded to `alignment`.
+pub fn find_build_id(
+    data: &[u8], alignment: u64, endian: Endian,
+) -> Result<Option<&[u8]>, Error> {
+    let alignment = if alignment == 8 { 8 } else { 4 };
+    let mut offset = 0;
+    while offset < data.len() {
+        let note = tail(data, offset)?;
+        let n_namesz: u32 = note.pread_with(0, endian)?;
+        let n_descsz: u32 = note.pread_with(4, endian)?;
+        let n_type: u32 = note.pread_with(8, endian)?;
+        let name_end = advance(12, n_namesz as usize)?;
+        let desc_start = advance(name_end, padding(name_end, alignment))?;
+        let desc_end = advance(desc_start, n_descsz as usize)?;
+        let name = note.get(12..name_end);
+        let desc = note.get(desc_start..desc_end)
+            .ok_or_else(|| format_err!("Note at {} is out of bounds", offset))?;
+        if n_type == NT_GNU_BUILD_ID && name == Some(&b"GNU\0"[..]) {
+            return Ok(Some(desc));
+        }
+        offset = advance(offset, advance(desc_end, padding(desc_end, alignment))?)?;
+    }
+    Ok(None)
+}
+
+/// NT_GNU_BUILD_ID as a lowercase hex string. A malformed note section does
+/// not hide a build id in the notes that follow it.
+pub fn build_id(elf: &Elf, bytes: &[u8]) -> Option<String> {
+    let endian = if elf.little_endian { Endian::Little } else { Endian::Big };
+    let sections = elf.section_headers.iter()
+        .filter(|shdr| shdr.sh_type == SHT_NOTE)
+        .map(|shdr| (shdr.sh_offset, shdr.sh_size, shdr.sh_addralign));
+    // Section headers may be stripped, but the loader still needs the segments
+    let segments = elf.program_headers.iter()
+        .filter(|phdr| phdr.p_type == PT_NOTE)
+        .map(|phdr| (phdr.p_offset, phdr.p_filesz, phdr.p_align));
+    for (offset, size, alignment) in sections.chain(segments) {
+        let notes = file_data(bytes, offset, size)
+            .and_then(|data| find_build_id(data, alignment, endian));
+        match notes {
+            Ok(Some(build_id)) => return Some(hex::encode(build_id)),
+            Ok(None) => {}
+            Err(e) => warn!("Could not parse ELF notes at offset {}: {}", offset, e),
+        }
+    }
+    None
+}
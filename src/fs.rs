@@ -1,5 +1,5 @@
-use std::fs::{create_dir_all, File};
-use std::path::Path;
+use std::fs::{create_dir_all, File, symlink_metadata};
+use std::path::{Component, Path, PathBuf};
 
 use failure::{bail, Error, ResultExt};
 
@@ -14,3 +14,25 @@ pub fn create_file_all(path: &Path) -> Result<File, Error> {
         .with_context(|_| format!("File::create({:?}) failed", path))
         .map_err(Error::from)
 }
+
+/// Maps an absolute archive path into `directory`, refusing anything that
+/// would end up outside of it, including paths that go through symlinks.
+pub fn target_path(directory: &Path, path: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    let mut target = directory.to_owned();
+    for component in relative.components() {
+        match component {
+            Component::Normal(t) => {
+                let is_symlink = symlink_metadata(&target)
+                    .map(|m| m.file_type().is_symlink())
+                    .unwrap_or(false);
+                if target.as_path() != directory && is_symlink {
+                    bail!("Refusing to extract {} through a symlink {:?}", path, target);
+                }
+                target.push(t);
+            }
+            _ => bail!("Refusing to extract {} outside of {:?}", path, directory),
+        }
+    }
+    Ok(target)
+}
@@ -1,22 +1,24 @@
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use failure::{bail, Error, ResultExt};
-use sha2::{Digest, Sha256};
+use digest::DynDigest;
+use failure::{bail, Error, format_err, ResultExt};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use tokio_io::AsyncRead;
 
-trait Hash {
-    fn update(&mut self, buf: &[u8]);
-    fn hexdigest(self) -> String;
-}
-
-impl<T> Hash for T where T: Digest {
-    fn update(&mut self, buf: &[u8]) {
-        self.input(buf);
-    }
-
-    fn hexdigest(self) -> String {
-        hex::encode(self.result())
+pub fn make_digest(hash_type: &str) -> Result<Box<DynDigest + Send>, Error> {
+    match hash_type {
+        "md5" => Ok(Box::new(Md5::new())),
+        "sha" | "sha1" => Ok(Box::new(Sha1::new())),
+        "sha224" => Ok(Box::new(Sha224::new())),
+        "sha256" => Ok(Box::new(Sha256::new())),
+        "sha384" => Ok(Box::new(Sha384::new())),
+        "sha512" => Ok(Box::new(Sha512::new())),
+        _ => bail!("Unsupported hash type: {}", hash_type),
     }
 }
 
@@ -25,20 +27,75 @@ pub fn hexdigest_path(path: &Path, hash_type: &str) -> Result<String, Error> {
     hexdigest_file(file, hash_type)
 }
 
-fn hexdigest_file_1<H>(mut file: File, mut hash: H) -> Result<String, Error> where H: Hash {
+fn hexdigest_file(mut file: File, hash_type: &str) -> Result<String, Error> {
+    let mut hash = make_digest(hash_type)?;
     let mut buf = [0 as u8; 8192];
     loop {
         let n = file.read(&mut buf).context("File::read() failed")?;
         if n == 0 {
-            break Ok(hash.hexdigest());
+            break Ok(hex::encode(hash.result()));
         }
-        hash.update(&buf[0..n]);
+        hash.input(&buf[0..n]);
     }
 }
 
-fn hexdigest_file(file: File, hash_type: &str) -> Result<String, Error> {
-    match hash_type {
-        "sha256" => hexdigest_file_1(file, Sha256::new()),
-        _ => bail!("Unsupported hash type: {}", hash_type),
+/// A digest that can be updated by a reader and queried by someone else.
+#[derive(Clone)]
+pub struct SharedDigest {
+    hash_type: String,
+    hash: Arc<Mutex<Box<DynDigest + Send>>>,
+}
+
+impl SharedDigest {
+    pub fn new(hash_type: &str) -> Result<SharedDigest, Error> {
+        Ok(SharedDigest {
+            hash_type: hash_type.to_owned(),
+            hash: Arc::new(Mutex::new(make_digest(hash_type)?)),
+        })
+    }
+
+    pub fn hash_type(&self) -> &str {
+        &self.hash_type
+    }
+
+    pub fn input(&self, buf: &[u8]) -> Result<(), Error> {
+        self.hash
+            .lock()
+            .map_err(|_| format_err!("Failed to lock a digest"))?
+            .input(buf);
+        Ok(())
+    }
+
+    pub fn hexdigest(&self) -> Result<String, Error> {
+        let hash = self.hash
+            .lock()
+            .map_err(|_| format_err!("Failed to lock a digest"))?
+            .box_clone();
+        Ok(hex::encode(hash.result()))
     }
 }
+
+/// Passes everything that is read from `inner` to `digests`.
+pub struct DigestReader<R> {
+    inner: R,
+    digests: Vec<SharedDigest>,
+}
+
+impl<R> DigestReader<R> {
+    pub fn new(inner: R, digests: Vec<SharedDigest>) -> DigestReader<R> {
+        DigestReader { inner, digests }
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for digest in &self.digests {
+            digest.input(&buf[..n])
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+        Ok(n)
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for DigestReader<R> {}
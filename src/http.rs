@@ -1,6 +1,12 @@
+use std::cmp::min;
+use std::io::{self, Read};
+
 use failure::{bail, Error, ResultExt};
+use futures::{Async, Stream};
+use hyper::{Body, Chunk};
 use hyper_tls::HttpsConnector;
 use log::info;
+use tokio_io::AsyncRead;
 use tokio_sync::semaphore::Semaphore;
 
 use crate::errors::FutureExt;
@@ -34,3 +40,38 @@ pub async fn checked_fetch<'a>(
         bail!("Failed to fetch {}: status-code {}", &uri, status);
     }
 }
+
+/// Reads a response body as it arrives, without buffering all of it.
+pub struct BodyReader {
+    body: Body,
+    chunk: Chunk,
+    offset: usize,
+}
+
+impl BodyReader {
+    pub fn new(body: Body) -> BodyReader {
+        BodyReader { body, chunk: Chunk::default(), offset: 0 }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.chunk.len() {
+            match self.body.poll() {
+                Ok(Async::Ready(Some(chunk))) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                }
+                Ok(Async::Ready(None)) => return Ok(0),
+                Ok(Async::NotReady) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+        let n = min(buf.len(), self.chunk.len() - self.offset);
+        buf[..n].copy_from_slice(&self.chunk[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl AsyncRead for BodyReader {}
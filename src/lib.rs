@@ -12,13 +12,16 @@ pub mod clap;
 pub mod db;
 pub mod cpio;
 pub mod decoders;
+pub mod elf;
 pub mod fs;
 pub mod hashes;
 pub mod http;
+pub mod limits;
 pub mod metrics;
 pub mod models;
 pub mod repomd;
 pub mod rpm;
 pub mod schema;
+pub mod signatures;
 pub mod sync;
 pub mod tokio;
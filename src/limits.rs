@@ -0,0 +1,68 @@
+use std::sync::RwLock;
+
+use failure::{bail, Error, format_err};
+use lazy_static::lazy_static;
+
+/// Upper bounds for the sizes that packages declare, checked before anything
+/// is allocated.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// rpm itself allows 0xffff (HEADER_TAGS_MAX).
+    pub max_index_entries: u32,
+    /// rpm itself allows 256 MiB (HEADER_DATA_MAX).
+    pub max_store_size: u32,
+    pub max_name_size: u64,
+    /// The largest cpio entry that is read into memory.
+    pub max_entry_data_size: u64,
+    /// index-repo spools larger ELF files to disk instead of reading them
+    /// into memory.
+    pub spool_threshold: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_index_entries: 0xffff,
+            max_store_size: 256 * 1024 * 1024,
+            max_name_size: 4096,
+            max_entry_data_size: 1024 * 1024 * 1024,
+            spool_threshold: 64 * 1024 * 1024,
+        }
+    }
+}
+
+lazy_static! {
+    static ref LIMITS: RwLock<Limits> = RwLock::new(Limits::default());
+}
+
+pub fn limits() -> Limits {
+    match LIMITS.read() {
+        Ok(t) => *t,
+        Err(e) => *e.into_inner(),
+    }
+}
+
+pub fn set_limits(limits: Limits) -> Result<(), Error> {
+    *LIMITS.write().map_err(|_| format_err!("Failed to lock limits"))? = limits;
+    Ok(())
+}
+
+pub fn check_limit(what: &str, value: u64, limit: u64) -> Result<(), Error> {
+    if value > limit {
+        bail!("{} {} exceeds the limit of {}", what, value, limit);
+    }
+    Ok(())
+}
+
+/// The number of bytes that follow `pos` up to the next multiple of
+/// `alignment`.
+pub fn padding(pos: usize, alignment: usize) -> usize {
+    match pos % alignment {
+        0 => 0,
+        rem => alignment - rem,
+    }
+}
+
+pub fn advance(pos: usize, n: usize) -> Result<usize, Error> {
+    pos.checked_add(n).ok_or_else(|| format_err!("Position {} + {} overflows", pos, n))
+}
@@ -33,8 +33,19 @@ impl Debug for Size {
 #[derive(Clone, Default)]
 pub struct Metrics {
     pub elapsed_time: Duration,
+    pub failed_packages_count: usize,
     pub indexed_packages_count: usize,
     pub indexed_packages_size: Size,
+    pub spooled_files_count: usize,
+    pub spooled_files_size: Size,
+    pub sql_demangled_names_insert_count: usize,
+    pub sql_demangled_names_insert_time: Duration,
+    pub sql_dependencies_insert_count: usize,
+    pub sql_dependencies_insert_time: Duration,
+    pub sql_dynamic_entries_insert_count: usize,
+    pub sql_dynamic_entries_insert_time: Duration,
+    pub sql_elf_files_insert_count: usize,
+    pub sql_elf_files_insert_time: Duration,
     pub sql_files_insert_count: usize,
     pub sql_files_insert_time: Duration,
     pub sql_mutex_acquisition_count: usize,
@@ -116,8 +127,19 @@ pub fn log_metrics() -> Result<(), Error> {
             &current,
             (
                 elapsed_time,
+                failed_packages_count,
                 indexed_packages_count,
                 indexed_packages_size,
+                spooled_files_count,
+                spooled_files_size,
+                sql_demangled_names_insert_count,
+                sql_demangled_names_insert_time,
+                sql_dependencies_insert_count,
+                sql_dependencies_insert_time,
+                sql_dynamic_entries_insert_count,
+                sql_dynamic_entries_insert_time,
+                sql_elf_files_insert_count,
+                sql_elf_files_insert_time,
                 sql_files_insert_count,
                 sql_files_insert_time,
                 sql_mutex_acquisition_count,
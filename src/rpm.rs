@@ -1,14 +1,22 @@
 use std::collections::HashMap;
+use std::io::{self, Read};
 use std::str::from_utf8;
 
 use arrayref::array_ref;
+use bzip2::read::BzDecoder;
 use failure::{bail, Error, format_err, ResultExt};
+use flate2::read::GzDecoder;
 use nom::{be_u16, be_u32, be_u8, do_parse, named, tag, take};
 use tokio_io::AsyncRead;
-use tokio_io::io::read_exact;
+use tokio_io::io::{read_exact, read_to_end};
 use xz2::read::XzDecoder;
+use xz2::stream::Stream as XzStream;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
+use crate::cpio::Archive;
 use crate::errors::FutureExt;
+use crate::hashes::{DigestReader, SharedDigest};
+use crate::limits::{advance, check_limit, limits, padding};
 
 pub struct Lead {
     pub magic: [u8; 4],
@@ -26,7 +34,7 @@ static LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
 
 static LEAD_SIZE: usize = 96;
 
-named!(parse_lead<Lead>,
+named!(pub parse_lead<Lead>,
     do_parse!(
         tag!(LEAD_MAGIC.as_ref()) >>
         major: be_u8 >>
@@ -72,7 +80,7 @@ pub struct Header {
     pub store_size: u32,
 }
 
-named!(parse_header<Header>,
+named!(pub parse_header<Header>,
     do_parse!(
         tag!(HEADER_MAGIC.as_ref()) >>
         version: be_u8 >>
@@ -91,16 +99,17 @@ named!(parse_header<Header>,
 pub async fn read_header<A: AsyncRead + Send + 'static>(
     a: A, pos: usize,
 ) -> Result<(A, usize, Header), Error> {
-    let padding = ((pos + 7) & !7) - pos;
+    let padding = padding(pos, 8);
     let (a, _) = await_old!(read_exact(a, vec![0u8; padding])
         .context("Could not pad RPM header"))?;
     let (a, buf) = await_old!(read_exact(a, vec![0u8; HEADER_SIZE])
             .context("Could not read RPM header"))?;
     let (_, header) = parse_header(&buf)
         .map_err(|_| format_err!("Could not parse RPM header - bad magic?"))?;
-    Ok((a, pos + padding + HEADER_SIZE, header))
+    Ok((a, advance(pos, padding + HEADER_SIZE)?, header))
 }
 
+#[derive(Clone, Copy)]
 pub struct IndexEntry {
     pub tag: u32,
     pub tpe: u32,
@@ -110,7 +119,7 @@ pub struct IndexEntry {
 
 static INDEX_ENTRY_SIZE: usize = 16;
 
-named!(parse_index_entry<IndexEntry>,
+named!(pub parse_index_entry<IndexEntry>,
     do_parse!(
         tag: be_u32 >>
         tpe: be_u32 >>
@@ -124,75 +133,1041 @@ named!(parse_index_entry<IndexEntry>,
         }))
 );
 
-pub async fn read_index_entry<A: AsyncRead + Send + 'static>(
-    a: A, pos: usize,
-) -> Result<(A, usize, IndexEntry), Error> {
-    let (a, buf) = await_old!(read_exact(a, vec![0u8; INDEX_ENTRY_SIZE])
-        .context("Could not read RPM index entry"))?;
-    let (_, index_entry) = parse_index_entry(&buf)
-        .map_err(|_| format_err!("Could not parse RPM index entry"))?;
-    Ok((a, pos + INDEX_ENTRY_SIZE, index_entry))
+pub const TYPE_NULL: u32 = 0;
+pub const TYPE_CHAR: u32 = 1;
+pub const TYPE_INT8: u32 = 2;
+pub const TYPE_INT16: u32 = 3;
+pub const TYPE_INT32: u32 = 4;
+pub const TYPE_INT64: u32 = 5;
+pub const TYPE_STRING: u32 = 6;
+pub const TYPE_BIN: u32 = 7;
+pub const TYPE_STRING_ARRAY: u32 = 8;
+pub const TYPE_I18NSTRING: u32 = 9;
+
+pub const TAG_HEADERI18NTABLE: u32 = 100;
+
+pub const SIGTAG_DSA: u32 = 267;
+pub const SIGTAG_RSA: u32 = 268;
+pub const SIGTAG_SHA1: u32 = 269;
+pub const SIGTAG_SHA256: u32 = 273;
+pub const SIGTAG_PGP: u32 = 1002;
+pub const SIGTAG_MD5: u32 = 1004;
+pub const SIGTAG_GPG: u32 = 1005;
+
+pub const TAG_NAME: u32 = 1000;
+pub const TAG_VERSION: u32 = 1001;
+pub const TAG_RELEASE: u32 = 1002;
+pub const TAG_EPOCH: u32 = 1003;
+pub const TAG_SUMMARY: u32 = 1004;
+pub const TAG_DESCRIPTION: u32 = 1005;
+pub const TAG_BUILDTIME: u32 = 1006;
+pub const TAG_BUILDHOST: u32 = 1007;
+pub const TAG_SIZE: u32 = 1009;
+pub const TAG_VENDOR: u32 = 1011;
+pub const TAG_LICENSE: u32 = 1014;
+pub const TAG_PACKAGER: u32 = 1015;
+pub const TAG_GROUP: u32 = 1016;
+pub const TAG_SOURCE: u32 = 1018;
+pub const TAG_PATCH: u32 = 1019;
+pub const TAG_URL: u32 = 1020;
+pub const TAG_ARCH: u32 = 1022;
+pub const TAG_OLDFILENAMES: u32 = 1027;
+pub const TAG_FILESIZES: u32 = 1028;
+pub const TAG_FILEMODES: u32 = 1030;
+pub const TAG_FILEMTIMES: u32 = 1034;
+pub const TAG_FILEDIGESTS: u32 = 1035;
+pub const TAG_FILELINKTOS: u32 = 1036;
+pub const TAG_FILEFLAGS: u32 = 1037;
+pub const TAG_FILEUSERNAME: u32 = 1039;
+pub const TAG_FILEGROUPNAME: u32 = 1040;
+pub const TAG_SOURCERPM: u32 = 1044;
+pub const TAG_PROVIDENAME: u32 = 1047;
+pub const TAG_REQUIREFLAGS: u32 = 1048;
+pub const TAG_REQUIRENAME: u32 = 1049;
+pub const TAG_REQUIREVERSION: u32 = 1050;
+pub const TAG_CONFLICTFLAGS: u32 = 1053;
+pub const TAG_CONFLICTNAME: u32 = 1054;
+pub const TAG_CONFLICTVERSION: u32 = 1055;
+pub const TAG_OBSOLETENAME: u32 = 1090;
+pub const TAG_FILEDEVICES: u32 = 1095;
+pub const TAG_FILEINODES: u32 = 1096;
+pub const TAG_PROVIDEFLAGS: u32 = 1112;
+pub const TAG_PROVIDEVERSION: u32 = 1113;
+pub const TAG_OBSOLETEFLAGS: u32 = 1114;
+pub const TAG_OBSOLETEVERSION: u32 = 1115;
+pub const TAG_DIRINDEXES: u32 = 1116;
+pub const TAG_BASENAMES: u32 = 1117;
+pub const TAG_DIRNAMES: u32 = 1118;
+pub const TAG_PAYLOADFORMAT: u32 = 1124;
+pub const TAG_PAYLOADCOMPRESSOR: u32 = 1125;
+pub const TAG_PAYLOADFLAGS: u32 = 1126;
+pub const TAG_LONGFILESIZES: u32 = 5008;
+pub const TAG_LONGSIZE: u32 = 5009;
+pub const TAG_RECOMMENDNAME: u32 = 5046;
+pub const TAG_RECOMMENDVERSION: u32 = 5047;
+pub const TAG_RECOMMENDFLAGS: u32 = 5048;
+pub const TAG_SUGGESTNAME: u32 = 5049;
+pub const TAG_SUGGESTVERSION: u32 = 5050;
+pub const TAG_SUGGESTFLAGS: u32 = 5051;
+pub const TAG_SUPPLEMENTNAME: u32 = 5052;
+pub const TAG_SUPPLEMENTVERSION: u32 = 5053;
+pub const TAG_SUPPLEMENTFLAGS: u32 = 5054;
+pub const TAG_ENHANCENAME: u32 = 5055;
+pub const TAG_ENHANCEVERSION: u32 = 5056;
+pub const TAG_ENHANCEFLAGS: u32 = 5057;
+pub const TAG_PAYLOADDIGEST: u32 = 5092;
+pub const TAG_PAYLOADDIGESTALGO: u32 = 5093;
+pub const TAG_PAYLOADDIGESTALT: u32 = 5097;
+
+pub const FILE_CONFIG: u32 = 1 << 0;
+pub const FILE_DOC: u32 = 1 << 1;
+pub const FILE_ICON: u32 = 1 << 2;
+pub const FILE_MISSINGOK: u32 = 1 << 3;
+pub const FILE_NOREPLACE: u32 = 1 << 4;
+pub const FILE_SPECFILE: u32 = 1 << 5;
+pub const FILE_GHOST: u32 = 1 << 6;
+pub const FILE_LICENSE: u32 = 1 << 7;
+pub const FILE_README: u32 = 1 << 8;
+
+pub const SENSE_LESS: u32 = 1 << 1;
+pub const SENSE_GREATER: u32 = 1 << 2;
+pub const SENSE_EQUAL: u32 = 1 << 3;
+
+static TAG_NAMES: &[(u32, &str)] = &[
+    (61, "HEADERIMAGE"),
+    (62, "HEADERSIGNATURES"),
+    (63, "HEADERIMMUTABLE"),
+    (64, "HEADERREGIONS"),
+    (100, "HEADERI18NTABLE"),
+    (261, "SIGMD5"),
+    (262, "SIGGPG"),
+    (267, "DSAHEADER"),
+    (268, "RSAHEADER"),
+    (269, "SHA1HEADER"),
+    (270, "LONGSIGSIZE"),
+    (271, "LONGARCHIVESIZE"),
+    (273, "SHA256HEADER"),
+    (1000, "NAME"),
+    (1001, "VERSION"),
+    (1002, "RELEASE"),
+    (1003, "EPOCH"),
+    (1004, "SUMMARY"),
+    (1005, "DESCRIPTION"),
+    (1006, "BUILDTIME"),
+    (1007, "BUILDHOST"),
+    (1008, "INSTALLTIME"),
+    (1009, "SIZE"),
+    (1010, "DISTRIBUTION"),
+    (1011, "VENDOR"),
+    (1014, "LICENSE"),
+    (1015, "PACKAGER"),
+    (1016, "GROUP"),
+    (1017, "CHANGELOG"),
+    (1018, "SOURCE"),
+    (1019, "PATCH"),
+    (1020, "URL"),
+    (1021, "OS"),
+    (1022, "ARCH"),
+    (1023, "PREIN"),
+    (1024, "POSTIN"),
+    (1025, "PREUN"),
+    (1026, "POSTUN"),
+    (1027, "OLDFILENAMES"),
+    (1028, "FILESIZES"),
+    (1029, "FILESTATES"),
+    (1030, "FILEMODES"),
+    (1033, "FILERDEVS"),
+    (1034, "FILEMTIMES"),
+    (1035, "FILEDIGESTS"),
+    (1036, "FILELINKTOS"),
+    (1037, "FILEFLAGS"),
+    (1039, "FILEUSERNAME"),
+    (1040, "FILEGROUPNAME"),
+    (1044, "SOURCERPM"),
+    (1045, "FILEVERIFYFLAGS"),
+    (1046, "ARCHIVESIZE"),
+    (1047, "PROVIDENAME"),
+    (1048, "REQUIREFLAGS"),
+    (1049, "REQUIRENAME"),
+    (1050, "REQUIREVERSION"),
+    (1051, "NOSOURCE"),
+    (1052, "NOPATCH"),
+    (1053, "CONFLICTFLAGS"),
+    (1054, "CONFLICTNAME"),
+    (1055, "CONFLICTVERSION"),
+    (1057, "BUILDROOT"),
+    (1059, "EXCLUDEARCH"),
+    (1060, "EXCLUDEOS"),
+    (1061, "EXCLUSIVEARCH"),
+    (1062, "EXCLUSIVEOS"),
+    (1064, "RPMVERSION"),
+    (1065, "TRIGGERSCRIPTS"),
+    (1066, "TRIGGERNAME"),
+    (1067, "TRIGGERVERSION"),
+    (1068, "TRIGGERFLAGS"),
+    (1069, "TRIGGERINDEX"),
+    (1079, "VERIFYSCRIPT"),
+    (1080, "CHANGELOGTIME"),
+    (1081, "CHANGELOGNAME"),
+    (1082, "CHANGELOGTEXT"),
+    (1085, "PREINPROG"),
+    (1086, "POSTINPROG"),
+    (1087, "PREUNPROG"),
+    (1088, "POSTUNPROG"),
+    (1089, "BUILDARCHS"),
+    (1090, "OBSOLETENAME"),
+    (1091, "VERIFYSCRIPTPROG"),
+    (1092, "TRIGGERSCRIPTPROG"),
+    (1094, "COOKIE"),
+    (1095, "FILEDEVICES"),
+    (1096, "FILEINODES"),
+    (1097, "FILELANGS"),
+    (1098, "PREFIXES"),
+    (1099, "INSTPREFIXES"),
+    (1106, "SOURCEPACKAGE"),
+    (1112, "PROVIDEFLAGS"),
+    (1113, "PROVIDEVERSION"),
+    (1114, "OBSOLETEFLAGS"),
+    (1115, "OBSOLETEVERSION"),
+    (1116, "DIRINDEXES"),
+    (1117, "BASENAMES"),
+    (1118, "DIRNAMES"),
+    (1122, "OPTFLAGS"),
+    (1123, "DISTURL"),
+    (1124, "PAYLOADFORMAT"),
+    (1125, "PAYLOADCOMPRESSOR"),
+    (1126, "PAYLOADFLAGS"),
+    (1131, "RHNPLATFORM"),
+    (1132, "PLATFORM"),
+    (1140, "FILECOLORS"),
+    (1141, "FILECLASS"),
+    (1142, "CLASSDICT"),
+    (1143, "FILEDEPENDSX"),
+    (1144, "FILEDEPENDSN"),
+    (1145, "DEPENDSDICT"),
+    (1146, "SOURCEPKGID"),
+    (1151, "PRETRANS"),
+    (1152, "POSTTRANS"),
+    (1153, "PRETRANSPROG"),
+    (1154, "POSTTRANSPROG"),
+    (1155, "DISTTAG"),
+    (5008, "LONGFILESIZES"),
+    (5009, "LONGSIZE"),
+    (5010, "FILECAPS"),
+    (5011, "FILEDIGESTALGO"),
+    (5012, "BUGURL"),
+    (5034, "HEADERCOLOR"),
+    (5046, "RECOMMENDNAME"),
+    (5047, "RECOMMENDVERSION"),
+    (5048, "RECOMMENDFLAGS"),
+    (5049, "SUGGESTNAME"),
+    (5050, "SUGGESTVERSION"),
+    (5051, "SUGGESTFLAGS"),
+    (5052, "SUPPLEMENTNAME"),
+    (5053, "SUPPLEMENTVERSION"),
+    (5054, "SUPPLEMENTFLAGS"),
+    (5055, "ENHANCENAME"),
+    (5056, "ENHANCEVERSION"),
+    (5057, "ENHANCEFLAGS"),
+    (5062, "ENCODING"),
+    (5092, "PAYLOADDIGEST"),
+    (5093, "PAYLOADDIGESTALGO"),
+    (5097, "PAYLOADDIGESTALT"),
+];
+
+static SIGTAG_NAMES: &[(u32, &str)] = &[
+    (62, "HEADERSIGNATURES"),
+    (267, "DSAHEADER"),
+    (268, "RSAHEADER"),
+    (269, "SHA1HEADER"),
+    (270, "LONGSIZE"),
+    (271, "LONGARCHIVESIZE"),
+    (273, "SHA256HEADER"),
+    (1000, "SIZE"),
+    (1001, "LEMD5_1"),
+    (1002, "PGP"),
+    (1003, "LEMD5_2"),
+    (1004, "MD5"),
+    (1005, "GPG"),
+    (1006, "PGP5"),
+    (1007, "PAYLOADSIZE"),
+    (1008, "RESERVEDSPACE"),
+];
+
+pub fn tag_name(tag: u32) -> Option<&'static str> {
+    TAG_NAMES.iter().find(|(t, _)| *t == tag).map(|(_, name)| *name)
+}
+
+pub fn signature_tag_name(tag: u32) -> Option<&'static str> {
+    SIGTAG_NAMES.iter().find(|(t, _)| *t == tag).map(|(_, name)| *name)
+}
+
+pub fn type_name(tpe: u32) -> Option<&'static str> {
+    match tpe {
+        TYPE_NULL => Some("NULL"),
+        TYPE_CHAR => Some("CHAR"),
+        TYPE_INT8 => Some("INT8"),
+        TYPE_INT16 => Some("INT16"),
+        TYPE_INT32 => Some("INT32"),
+        TYPE_INT64 => Some("INT64"),
+        TYPE_STRING => Some("STRING"),
+        TYPE_BIN => Some("BIN"),
+        TYPE_STRING_ARRAY => Some("STRING_ARRAY"),
+        TYPE_I18NSTRING => Some("I18NSTRING"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagValue {
+    Null,
+    Char(Vec<u8>),
+    Int8(Vec<u8>),
+    Int16(Vec<u16>),
+    Int32(Vec<u32>),
+    Int64(Vec<u64>),
+    String(String),
+    Bin(Vec<u8>),
+    StringArray(Vec<String>),
+    I18nString(Vec<String>),
 }
 
 pub struct FullHeader {
     pub header: Header,
     pub index_entries: HashMap<u32, IndexEntry>,
+    pub index: Vec<u8>,
     pub store: Vec<u8>,
 }
 
 impl FullHeader {
-    pub fn get_string_tag(&self, tag: u32, default: &str) -> Result<String, Error> {
+    /// Size of the header as stored in the package, without the leading padding.
+    pub fn size(&self) -> usize {
+        HEADER_SIZE + self.index.len() + self.store.len()
+    }
+
+    /// The header bytes exactly as stored in the package, which are covered
+    /// by the header digests and signatures.
+    pub fn raw(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.size());
+        raw.extend_from_slice(&self.header.magic);
+        raw.push(self.header.version);
+        raw.extend_from_slice(&self.header.reserved);
+        raw.extend_from_slice(&self.header.index_entry_count.to_be_bytes());
+        raw.extend_from_slice(&self.header.store_size.to_be_bytes());
+        raw.extend_from_slice(&self.index);
+        raw.extend_from_slice(&self.store);
+        raw
+    }
+
+    /// Same check as rpm's headerIsSource().
+    pub fn is_source(&self) -> bool {
+        !self.index_entries.contains_key(&TAG_SOURCERPM)
+    }
+
+    fn get_entry(&self, tag: u32, tpes: &[u32]) -> Result<Option<&IndexEntry>, Error> {
         let entry = match self.index_entries.get(&tag) {
             Some(t) => t,
-            None => return Ok(default.to_owned()),
+            None => return Ok(None),
+        };
+        if !tpes.contains(&entry.tpe) {
+            bail!("RPM tag {} has type {}, expected one of {:?}", tag, entry.tpe, tpes);
+        }
+        Ok(Some(entry))
+    }
+
+    fn get_bytes(&self, entry: &IndexEntry, item_size: usize) -> Result<&[u8], Error> {
+        let start = entry.offset as usize;
+        let end = (entry.count as usize)
+            .checked_mul(item_size)
+            .and_then(|size| size.checked_add(start))
+            .ok_or_else(|| format_err!("RPM tag {} size overflows", entry.tag))?;
+        if end > self.store.len() {
+            bail!("RPM tag {} points past the end of the store ({}..{} > {})",
+                  entry.tag, start, end, self.store.len());
+        }
+        Ok(&self.store[start..end])
+    }
+
+    fn get_strings(&self, entry: &IndexEntry) -> Result<Vec<String>, Error> {
+        let mut pos = entry.offset as usize;
+        let mut strings = Vec::new();
+        for _ in 0..entry.count {
+            if pos >= self.store.len() {
+                bail!("RPM tag {} points past the end of the store", entry.tag);
+            }
+            let len = self.store[pos..]
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or_else(|| format_err!("RPM tag {} has an unterminated string", entry.tag))?;
+            let s = from_utf8(&self.store[pos..pos + len])
+                .with_context(|_| format!("RPM tag {} points to malformed UTF-8", entry.tag))?;
+            strings.push(s.to_owned());
+            pos += len + 1;
+        }
+        Ok(strings)
+    }
+
+    pub fn get_tag(&self, tag: u32) -> Result<Option<TagValue>, Error> {
+        let entry = match self.index_entries.get(&tag) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let value = match entry.tpe {
+            TYPE_NULL => TagValue::Null,
+            TYPE_CHAR => TagValue::Char(self.get_bytes(entry, 1)?.to_vec()),
+            TYPE_INT8 => TagValue::Int8(self.get_bytes(entry, 1)?.to_vec()),
+            TYPE_INT16 => TagValue::Int16(self.get_bytes(entry, 2)?
+                .chunks(2)
+                .map(|b| u16::from_be_bytes(*array_ref![b, 0, 2]))
+                .collect()),
+            TYPE_INT32 => TagValue::Int32(self.get_bytes(entry, 4)?
+                .chunks(4)
+                .map(|b| u32::from_be_bytes(*array_ref![b, 0, 4]))
+                .collect()),
+            TYPE_INT64 => TagValue::Int64(self.get_bytes(entry, 8)?
+                .chunks(8)
+                .map(|b| u64::from_be_bytes(*array_ref![b, 0, 8]))
+                .collect()),
+            TYPE_STRING => {
+                let mut strings = self.get_strings(&IndexEntry { count: 1, ..*entry })?;
+                TagValue::String(strings.remove(0))
+            }
+            TYPE_BIN => TagValue::Bin(self.get_bytes(entry, 1)?.to_vec()),
+            TYPE_STRING_ARRAY => TagValue::StringArray(self.get_strings(entry)?),
+            TYPE_I18NSTRING => TagValue::I18nString(self.get_strings(entry)?),
+            tpe => bail!("RPM tag {} has unknown type {}", tag, tpe),
+        };
+        Ok(Some(value))
+    }
+
+    pub fn get_string_tag(&self, tag: u32, default: &str) -> Result<String, Error> {
+        match self.get_entry(tag, &[TYPE_STRING])? {
+            Some(entry) => {
+                let mut strings = self.get_strings(&IndexEntry { count: 1, ..*entry })?;
+                Ok(strings.remove(0))
+            }
+            None => Ok(default.to_owned()),
+        }
+    }
+
+    pub fn get_string_array_tag(&self, tag: u32) -> Result<Option<Vec<String>>, Error> {
+        match self.get_entry(tag, &[TYPE_STRING_ARRAY])? {
+            Some(entry) => Ok(Some(self.get_strings(entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Selects a translation the same way rpm does: exact locale match first,
+    /// then the locale with its codeset and modifier stripped, then its
+    /// language alone, and finally the first ("C") string.
+    pub fn get_i18n_string_tag(&self, tag: u32, locale: &str) -> Result<Option<String>, Error> {
+        let entry = match self.get_entry(tag, &[TYPE_STRING, TYPE_I18NSTRING])? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        if entry.tpe == TYPE_STRING {
+            return Ok(Some(self.get_string_tag(tag, "")?));
+        }
+        let mut strings = self.get_strings(entry)?;
+        if strings.is_empty() {
+            bail!("RPM tag {} has no strings", tag);
+        }
+        let locales = self.get_string_array_tag(TAG_HEADERI18NTABLE)?.unwrap_or_default();
+        let without_modifier = locale.split('@').next().unwrap_or(locale);
+        let without_codeset = without_modifier.split('.').next().unwrap_or(without_modifier);
+        let language = without_codeset.split('_').next().unwrap_or(without_codeset);
+        let index = [locale, without_codeset, language]
+            .iter()
+            .filter(|candidate| !candidate.is_empty())
+            .filter_map(|candidate| locales.iter().position(|l| l == candidate))
+            .find(|index| *index < strings.len())
+            .unwrap_or(0);
+        Ok(Some(strings.swap_remove(index)))
+    }
+
+    pub fn get_char_tag(&self, tag: u32) -> Result<Option<&[u8]>, Error> {
+        match self.get_entry(tag, &[TYPE_CHAR])? {
+            Some(entry) => Ok(Some(self.get_bytes(entry, 1)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_int8_tag(&self, tag: u32) -> Result<Option<&[u8]>, Error> {
+        match self.get_entry(tag, &[TYPE_INT8])? {
+            Some(entry) => Ok(Some(self.get_bytes(entry, 1)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_bin_tag(&self, tag: u32) -> Result<Option<&[u8]>, Error> {
+        match self.get_entry(tag, &[TYPE_BIN])? {
+            Some(entry) => Ok(Some(self.get_bytes(entry, 1)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_int16_tag(&self, tag: u32) -> Result<Option<Vec<u16>>, Error> {
+        match self.get_tag_of_type(tag, TYPE_INT16)? {
+            Some(TagValue::Int16(t)) => Ok(Some(t)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn get_int32_tag(&self, tag: u32) -> Result<Option<Vec<u32>>, Error> {
+        match self.get_tag_of_type(tag, TYPE_INT32)? {
+            Some(TagValue::Int32(t)) => Ok(Some(t)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn get_int64_tag(&self, tag: u32) -> Result<Option<Vec<u64>>, Error> {
+        match self.get_tag_of_type(tag, TYPE_INT64)? {
+            Some(TagValue::Int64(t)) => Ok(Some(t)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads an integer tag of any width, e.g. SIZE vs LONGSIZE.
+    pub fn get_int_tag(&self, tag: u32) -> Result<Option<Vec<u64>>, Error> {
+        match self.get_tag(tag)? {
+            None => Ok(None),
+            Some(TagValue::Char(t)) | Some(TagValue::Int8(t)) =>
+                Ok(Some(t.into_iter().map(u64::from).collect())),
+            Some(TagValue::Int16(t)) => Ok(Some(t.into_iter().map(u64::from).collect())),
+            Some(TagValue::Int32(t)) => Ok(Some(t.into_iter().map(u64::from).collect())),
+            Some(TagValue::Int64(t)) => Ok(Some(t)),
+            Some(_) => bail!("RPM tag {} is not an integer", tag),
+        }
+    }
+
+    fn get_tag_of_type(&self, tag: u32, tpe: u32) -> Result<Option<TagValue>, Error> {
+        match self.get_entry(tag, &[tpe])? {
+            Some(_) => self.get_tag(tag),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct PackageMetadata {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub url: Option<String>,
+    pub vendor: Option<String>,
+    pub packager: Option<String>,
+    pub buildhost: Option<String>,
+    pub buildtime: Option<i64>,
+    pub sourcerpm: Option<String>,
+    pub group: Option<String>,
+    pub installed_size: Option<i64>,
+}
+
+impl PackageMetadata {
+    pub fn from_header(header: &FullHeader) -> Result<PackageMetadata, Error> {
+        let string = |tag| header.get_i18n_string_tag(tag, "C");
+        let int = |tag| header.get_int_tag(tag)
+            .map(|values| values.and_then(|v| v.first().map(|x| *x as i64)));
+        Ok(PackageMetadata {
+            summary: string(TAG_SUMMARY)?,
+            description: string(TAG_DESCRIPTION)?,
+            license: string(TAG_LICENSE)?,
+            url: string(TAG_URL)?,
+            vendor: string(TAG_VENDOR)?,
+            packager: string(TAG_PACKAGER)?,
+            buildhost: string(TAG_BUILDHOST)?,
+            buildtime: int(TAG_BUILDTIME)?,
+            sourcerpm: string(TAG_SOURCERPM)?,
+            group: string(TAG_GROUP)?,
+            installed_size: match int(TAG_LONGSIZE)? {
+                Some(t) => Some(t),
+                None => int(TAG_SIZE)?,
+            },
+        })
+    }
+}
+
+pub struct FileInfo {
+    pub path: String,
+    pub mode: u16,
+    pub size: u64,
+    pub user_name: String,
+    pub group_name: String,
+    pub mtime: u32,
+    pub digest: String,
+    pub link_to: String,
+    pub flags: u32,
+    pub device: u32,
+    pub inode: u32,
+    /// Number of regular files in the payload that share device and inode.
+    pub nlink: u32,
+    /// Payloads store contents only for regular files and symlinks, not for
+    /// %ghost files, and store the contents of hardlinked files only once,
+    /// with the last link.
+    pub has_content: bool,
+}
+
+pub const S_IFMT: u16 = 0o170_000;
+pub const S_IFDIR: u16 = 0o040_000;
+pub const S_IFREG: u16 = 0o100_000;
+pub const S_IFLNK: u16 = 0o120_000;
+
+fn check_count<T>(
+    tag: u32, values: Option<Vec<T>>, count: usize,
+) -> Result<Option<Vec<T>>, Error> {
+    match values {
+        Some(ref t) if t.len() != count =>
+            bail!("RPM tag {} has {} values, expected {}", tag, t.len(), count),
+        values => Ok(values),
+    }
+}
+
+impl FileInfo {
+    pub fn from_header(header: &FullHeader) -> Result<Vec<FileInfo>, Error> {
+        let paths = match header.get_string_array_tag(TAG_BASENAMES)? {
+            Some(basenames) => {
+                let dirnames = header.get_string_array_tag(TAG_DIRNAMES)?
+                    .ok_or_else(|| format_err!("RPM header has BASENAMES, but no DIRNAMES"))?;
+                let dirindexes = header.get_int32_tag(TAG_DIRINDEXES)?
+                    .ok_or_else(|| format_err!("RPM header has BASENAMES, but no DIRINDEXES"))?;
+                if dirindexes.len() != basenames.len() {
+                    bail!("RPM header has {} BASENAMES, but {} DIRINDEXES",
+                          basenames.len(), dirindexes.len());
+                }
+                basenames
+                    .into_iter()
+                    .zip(dirindexes)
+                    .map(|(basename, dirindex)| match dirnames.get(dirindex as usize) {
+                        Some(dirname) => Ok(dirname.to_owned() + &basename),
+                        None => bail!("RPM DIRINDEXES value {} is out of bounds", dirindex),
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?
+            }
+            None => header.get_string_array_tag(TAG_OLDFILENAMES)?.unwrap_or_default(),
         };
-        if entry.tpe != 6 {
-            bail!("RPM index entry has incorrect type");
+        let count = paths.len();
+        let sizes = match header.get_int_tag(TAG_LONGFILESIZES)? {
+            Some(t) => Some(t),
+            None => header.get_int_tag(TAG_FILESIZES)?,
+        };
+        let sizes = check_count(TAG_FILESIZES, sizes, count)?;
+        let modes = check_count(
+            TAG_FILEMODES, header.get_int16_tag(TAG_FILEMODES)?, count)?;
+        let mtimes = check_count(
+            TAG_FILEMTIMES, header.get_int32_tag(TAG_FILEMTIMES)?, count)?;
+        let flags = check_count(
+            TAG_FILEFLAGS, header.get_int32_tag(TAG_FILEFLAGS)?, count)?;
+        let user_names = check_count(
+            TAG_FILEUSERNAME, header.get_string_array_tag(TAG_FILEUSERNAME)?, count)?;
+        let group_names = check_count(
+            TAG_FILEGROUPNAME, header.get_string_array_tag(TAG_FILEGROUPNAME)?, count)?;
+        let digests = check_count(
+            TAG_FILEDIGESTS, header.get_string_array_tag(TAG_FILEDIGESTS)?, count)?;
+        let link_tos = check_count(
+            TAG_FILELINKTOS, header.get_string_array_tag(TAG_FILELINKTOS)?, count)?;
+        let devices = check_count(
+            TAG_FILEDEVICES, header.get_int32_tag(TAG_FILEDEVICES)?, count)?;
+        let inodes = check_count(
+            TAG_FILEINODES, header.get_int32_tag(TAG_FILEINODES)?, count)?;
+        let mut files = paths
+            .into_iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let mode = modes.as_ref().map_or(0, |t| t[i]);
+                let flags = flags.as_ref().map_or(0, |t| t[i]);
+                FileInfo {
+                    path,
+                    mode,
+                    size: sizes.as_ref().map_or(0, |t| t[i]),
+                    user_name: user_names.as_ref().map_or_else(String::new, |t| t[i].clone()),
+                    group_name: group_names.as_ref().map_or_else(String::new, |t| t[i].clone()),
+                    mtime: mtimes.as_ref().map_or(0, |t| t[i]),
+                    digest: digests.as_ref().map_or_else(String::new, |t| t[i].clone()),
+                    link_to: link_tos.as_ref().map_or_else(String::new, |t| t[i].clone()),
+                    flags,
+                    device: devices.as_ref().map_or(0, |t| t[i]),
+                    inode: inodes.as_ref().map_or(i as u32, |t| t[i]),
+                    nlink: 1,
+                    has_content: flags & FILE_GHOST == 0 &&
+                        (mode & S_IFMT == S_IFREG || mode & S_IFMT == S_IFLNK),
+                }
+            })
+            .collect::<Vec<_>>();
+        FileInfo::count_links(&mut files);
+        Ok(files)
+    }
+
+    fn count_links(files: &mut [FileInfo]) {
+        let mut links: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (i, file) in files.iter().enumerate() {
+            if file.mode & S_IFMT == S_IFREG && file.flags & FILE_GHOST == 0 {
+                links.entry((file.device, file.inode)).or_default().push(i);
+            }
         }
-        if entry.offset as usize >= self.store.len() {
-            bail!("RPM index entry points past the end of the store");
+        for indices in links.values().filter(|indices| indices.len() > 1) {
+            for (n, i) in indices.iter().enumerate() {
+                files[*i].nlink = indices.len() as u32;
+                files[*i].has_content = n == indices.len() - 1;
+            }
+        }
+    }
+}
+
+pub struct Dependency {
+    pub kind: &'static str,
+    pub name: String,
+    pub flags: u32,
+    pub version: String,
+}
+
+static DEPENDENCY_TAGS: [(&str, u32, u32, u32); 8] = [
+    ("provides", TAG_PROVIDENAME, TAG_PROVIDEFLAGS, TAG_PROVIDEVERSION),
+    ("requires", TAG_REQUIRENAME, TAG_REQUIREFLAGS, TAG_REQUIREVERSION),
+    ("conflicts", TAG_CONFLICTNAME, TAG_CONFLICTFLAGS, TAG_CONFLICTVERSION),
+    ("obsoletes", TAG_OBSOLETENAME, TAG_OBSOLETEFLAGS, TAG_OBSOLETEVERSION),
+    ("recommends", TAG_RECOMMENDNAME, TAG_RECOMMENDFLAGS, TAG_RECOMMENDVERSION),
+    ("suggests", TAG_SUGGESTNAME, TAG_SUGGESTFLAGS, TAG_SUGGESTVERSION),
+    ("supplements", TAG_SUPPLEMENTNAME, TAG_SUPPLEMENTFLAGS, TAG_SUPPLEMENTVERSION),
+    ("enhances", TAG_ENHANCENAME, TAG_ENHANCEFLAGS, TAG_ENHANCEVERSION),
+];
+
+impl Dependency {
+    pub fn from_header(header: &FullHeader) -> Result<Vec<Dependency>, Error> {
+        let mut dependencies = Vec::new();
+        for (kind, name_tag, flags_tag, version_tag) in DEPENDENCY_TAGS.iter() {
+            let names = match header.get_string_array_tag(*name_tag)? {
+                Some(t) => t,
+                None => continue,
+            };
+            let count = names.len();
+            let flags = check_count(*flags_tag, header.get_int32_tag(*flags_tag)?, count)?;
+            let versions = check_count(
+                *version_tag, header.get_string_array_tag(*version_tag)?, count)?;
+            dependencies.extend(names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| Dependency {
+                    kind,
+                    name,
+                    flags: flags.as_ref().map_or(0, |t| t[i]),
+                    version: versions.as_ref().map_or_else(String::new, |t| t[i].clone()),
+                }));
         }
-        from_utf8(&self.store[entry.offset as usize..self.store.len()]
+        Ok(dependencies)
+    }
+}
+
+/// Source or patch file of a source package.
+pub struct SourceFile {
+    pub kind: &'static str,
+    pub name: String,
+    pub size: Option<u64>,
+    pub digest: Option<String>,
+}
+
+impl SourceFile {
+    /// Sizes and digests are taken from `files`, which is empty for
+    /// NoSource/NoPatch files.
+    pub fn from_header(header: &FullHeader, files: &[FileInfo]) -> Result<Vec<SourceFile>, Error> {
+        let files_by_name: HashMap<&str, &FileInfo> = files
             .iter()
-            .cloned()
-            .take_while(|b| *b != 0)
-            .collect::<Vec<_>>())
-            .context("RPM index entry points to malformed UTF-8")
-            .map_err(Error::from)
-            .map(std::borrow::ToOwned::to_owned)
+            .map(|f| (f.path.rsplit('/').next().unwrap_or(&f.path), f))
+            .collect();
+        let mut source_files = Vec::new();
+        for (kind, tag) in &[("source", TAG_SOURCE), ("patch", TAG_PATCH)] {
+            for name in header.get_string_array_tag(*tag)?.unwrap_or_default() {
+                let file = files_by_name.get(name.as_str());
+                source_files.push(SourceFile {
+                    kind,
+                    size: file.map(|f| f.size),
+                    digest: file.map(|f| f.digest.clone()),
+                    name,
+                });
+            }
+        }
+        Ok(source_files)
+    }
+}
+
+/// Unlike read_exact(), grows the buffer as the data arrives, so that sizes
+/// from truncated or malicious packages do not cost memory up front.
+async fn read_bytes<A: AsyncRead + Send + 'static>(
+    a: A, size: usize, what: &'static str,
+) -> Result<(A, Vec<u8>), Error> {
+    let (a, buf) = await_old!(read_to_end(a.take(size as u64), Vec::new())
+        .with_context(move |_| format!("Could not read RPM {}", what)))?;
+    if buf.len() != size {
+        bail!("Could not read RPM {}: unexpected end of file", what);
     }
+    Ok((a.into_inner(), buf))
+}
+
+/// Returns the index size, once the header is known to be sane.
+fn check_header_sizes(header: &Header) -> Result<usize, Error> {
+    let limits = limits();
+    check_limit("RPM index entry count",
+                u64::from(header.index_entry_count), u64::from(limits.max_index_entries))?;
+    check_limit("RPM store size",
+                u64::from(header.store_size), u64::from(limits.max_store_size))?;
+    (header.index_entry_count as usize)
+        .checked_mul(INDEX_ENTRY_SIZE)
+        .ok_or_else(|| format_err!("RPM index size overflows"))
+}
+
+fn parse_index(index: &[u8]) -> Result<HashMap<u32, IndexEntry>, Error> {
+    let mut index_entries = HashMap::with_capacity(index.len() / INDEX_ENTRY_SIZE);
+    for buf in index.chunks(INDEX_ENTRY_SIZE) {
+        let (_, index_entry) = parse_index_entry(buf)
+            .map_err(|_| format_err!("Could not parse RPM index entry"))?;
+        index_entries.insert(index_entry.tag, index_entry);
+    }
+    Ok(index_entries)
 }
 
 pub async fn read_full_header<A: AsyncRead + Send + 'static>(
     a: A, pos: usize,
 ) -> Result<(A, usize, FullHeader), Error> {
-    let (mut a, mut pos, header) = await!(read_header(a, pos))?;
-    let mut index_entries = HashMap::with_capacity(header.index_entry_count as usize);
-    for _ in 0..header.index_entry_count {
-        let (local_a, local_pos, index_entry) = await!(read_index_entry(a, pos))?;
-        index_entries.insert(index_entry.tag, index_entry);
-        a = local_a;
-        pos = local_pos;
+    let (a, pos, header) = await!(read_header(a, pos))?;
+    let index_size = check_header_sizes(&header)?;
+    let (a, index) = await_old!(read_exact(a, vec![0u8; index_size])
+        .context("Could not read RPM index"))?;
+    let index_entries = parse_index(&index)?;
+    let (a, store) = await!(read_bytes(a, header.store_size as usize, "store"))?;
+    let pos = advance(pos, index_size + header.store_size as usize)?;
+    Ok((a, pos, FullHeader { header, index_entries, index, store }))
+}
+
+struct DigestCheck {
+    name: &'static str,
+    expected: String,
+    actual: SharedDigest,
+}
+
+/// Digests from the signature header and the header, which are checked
+/// against the bytes that were actually read.
+pub struct Verifier {
+    header_checks: Vec<DigestCheck>,
+    payload_checks: Vec<DigestCheck>,
+}
+
+fn check_digests(checks: &[DigestCheck]) -> Result<(), Error> {
+    let mut mismatches = Vec::new();
+    for check in checks {
+        let actual = check.actual.hexdigest()?;
+        if actual != check.expected.to_lowercase() {
+            mismatches.push(format!("{} ({}): expected {}, got {}",
+                                    check.name, check.actual.hash_type(),
+                                    check.expected, actual));
+        }
+    }
+    if !mismatches.is_empty() {
+        bail!("RPM digest mismatch: {}", mismatches.join("; "));
+    }
+    Ok(())
+}
+
+impl Verifier {
+    pub fn verify_header(&self) -> Result<(), Error> {
+        check_digests(&self.header_checks)
+    }
+
+    /// Must be called after the payload has been read till the end.
+    pub fn verify_payload(&self) -> Result<(), Error> {
+        check_digests(&self.payload_checks)
+    }
+}
+
+fn pgp_hash_type(algo: u32) -> Result<&'static str, Error> {
+    match algo {
+        1 => Ok("md5"),
+        2 => Ok("sha1"),
+        8 => Ok("sha256"),
+        9 => Ok("sha384"),
+        10 => Ok("sha512"),
+        11 => Ok("sha224"),
+        _ => bail!("Unsupported PGP hash algorithm: {}", algo),
+    }
+}
+
+fn get_digest_tag(header: &FullHeader, tag: u32) -> Result<Option<String>, Error> {
+    match header.get_tag(tag)? {
+        None => Ok(None),
+        Some(TagValue::String(t)) => Ok(Some(t)),
+        Some(TagValue::StringArray(mut t)) if !t.is_empty() => Ok(Some(t.swap_remove(0))),
+        Some(TagValue::Bin(t)) => Ok(Some(hex::encode(t))),
+        Some(_) => bail!("RPM tag {} has unexpected type", tag),
+    }
+}
+
+/// Wires the digests from the signature header and the header into the
+/// payload stream and checks the digests of the header itself.
+fn make_verifier<A>(
+    a: A, signature_header: &FullHeader, header: &FullHeader,
+) -> Result<(DigestReader<A>, Vec<SharedDigest>, Verifier), Error> {
+    let mut header_checks = Vec::new();
+    let mut payload_checks = Vec::new();
+    let mut compressed_digests = Vec::new();
+    let mut uncompressed_digests = Vec::new();
+    let header_bytes = header.raw();
+    for (name, tag, hash_type) in &[
+        ("SHA256HEADER", SIGTAG_SHA256, "sha256"),
+        ("SHA1HEADER", SIGTAG_SHA1, "sha1"),
+    ] {
+        if let Some(expected) = get_digest_tag(signature_header, *tag)? {
+            let actual = SharedDigest::new(hash_type)?;
+            actual.input(&header_bytes)?;
+            header_checks.push(DigestCheck { name: *name, expected, actual });
+        }
+    }
+    if let Some(expected) = get_digest_tag(signature_header, SIGTAG_MD5)? {
+        let actual = SharedDigest::new("md5")?;
+        actual.input(&header_bytes)?;
+        compressed_digests.push(actual.clone());
+        payload_checks.push(DigestCheck { name: "MD5", expected, actual });
+    }
+    let payload_hash_type = match header.get_int32_tag(TAG_PAYLOADDIGESTALGO)? {
+        Some(algo) => match algo.first() {
+            Some(algo) => pgp_hash_type(*algo)?,
+            None => bail!("RPM tag {} is empty", TAG_PAYLOADDIGESTALGO),
+        },
+        None => "sha256",
+    };
+    if let Some(expected) = get_digest_tag(header, TAG_PAYLOADDIGEST)? {
+        let actual = SharedDigest::new(payload_hash_type)?;
+        compressed_digests.push(actual.clone());
+        payload_checks.push(DigestCheck { name: "PAYLOADDIGEST", expected, actual });
+    }
+    if let Some(expected) = get_digest_tag(header, TAG_PAYLOADDIGESTALT)? {
+        let actual = SharedDigest::new(payload_hash_type)?;
+        uncompressed_digests.push(actual.clone());
+        payload_checks.push(DigestCheck { name: "PAYLOADDIGESTALT", expected, actual });
+    }
+    let verifier = Verifier { header_checks, payload_checks };
+    Ok((DigestReader::new(a, compressed_digests), uncompressed_digests, verifier))
+}
+
+pub type Payload = Box<AsyncRead + Send + 'static>;
+
+fn get_payload_coding(header: &FullHeader) -> Result<String, Error> {
+    let format = header.get_string_tag(TAG_PAYLOADFORMAT, "cpio")?;
+    if format != "cpio" {
+        bail!("Unsupported RPM payload format: {}", format);
+    }
+    header.get_string_tag(TAG_PAYLOADCOMPRESSOR, "gzip")
+}
+
+// The decoders implement both Read and AsyncRead, only the boxes differ.
+macro_rules! decode_payload {
+    ($a:expr, $coding:expr) => {
+        match $coding.as_ref() {
+            "bzip2" => Box::new(BzDecoder::new($a)),
+            "gzip" => Box::new(GzDecoder::new($a)),
+            "lzma" => Box::new(XzDecoder::new_stream(
+                $a,
+                XzStream::new_lzma_decoder(std::u64::MAX)
+                    .context("Could not create an lzma decoder")?)),
+            "xz" => Box::new(XzDecoder::new($a)),
+            // PAYLOADFLAGS holds the compression level, which decoding does not need
+            "zstd" => Box::new(ZstdDecoder::new($a)
+                .context("Could not create a zstd decoder")?),
+            _ => bail!("Unsupported RPM payload coding: {}", $coding),
+        }
     }
-    let (a, store) = await_old!(read_exact(a, vec![0u8; header.store_size as usize])
-        .context("Could not read RPM store"))?;
-    Ok((a, pos + header.store_size as usize, FullHeader { header, index_entries, store }))
 }
 
 pub async fn read_all_headers<A: AsyncRead + Send + 'static>(
     a: A,
-) -> Result<(Box<AsyncRead + Send + 'static>, usize, Lead, FullHeader, FullHeader), Error> {
+) -> Result<(Payload, usize, Lead, FullHeader, FullHeader, Verifier), Error> {
     let (a, pos, lead) = await!(read_lead(a, 0))?;
     let (a, pos, signature_header) = await!(read_full_header(a, pos))?;
     let (a, pos, header) = await!(read_full_header(a, pos))?;
-    let format = header.get_string_tag(1124, "cpio")?;
-    if format != "cpio" {
-        bail!("Unsupported RPM payload format");
+    let (a, uncompressed_digests, verifier) = make_verifier(a, &signature_header, &header)?;
+    verifier.verify_header()?;
+    let coding = get_payload_coding(&header)?;
+    let a: Payload = decode_payload!(a, coding);
+    let a = Box::new(DigestReader::new(a, uncompressed_digests));
+    Ok((a, pos, lead, signature_header, header, verifier))
+}
+
+/// Reads the rest of the payload, e.g. the padding after the cpio trailer,
+/// and checks the payload digests.
+pub async fn verify_payload<A: AsyncRead + Send + 'static>(
+    a: A, verifier: Verifier,
+) -> Result<(), Error> {
+    await_old!(read_to_end(a, Vec::new())
+        .context("Could not read the end of RPM payload"))?;
+    verifier.verify_payload()
+}
+
+/// Blocking counterpart of read_bytes().
+fn read_vec<R: Read>(r: &mut R, size: usize, what: &str) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    r.take(size as u64)
+        .read_to_end(&mut buf)
+        .with_context(|_| format!("Could not read RPM {}", what))?;
+    if buf.len() != size {
+        bail!("Could not read RPM {}: unexpected end of file", what);
     }
-    let coding = header.get_string_tag(1125, "gzip")?;
-    let a: Box<AsyncRead + Send + 'static> = match coding.as_ref() {
-        "xz" => Box::new(XzDecoder::new(a)),
-        _ => bail!("Unsupported RPM payload coding"),
-    };
-    Ok((a, pos, lead, signature_header, header))
+    Ok(buf)
+}
+
+/// Blocking counterpart of read_full_header().
+pub fn read_full_header_sync<R: Read>(
+    r: &mut R, pos: usize,
+) -> Result<(usize, FullHeader), Error> {
+    let padding = padding(pos, 8);
+    read_vec(r, padding, "header padding")?;
+    let (_, header) = parse_header(&read_vec(r, HEADER_SIZE, "header")?)
+        .map_err(|_| format_err!("Could not parse RPM header - bad magic?"))?;
+    let index_size = check_header_sizes(&header)?;
+    let index = read_vec(r, index_size, "index")?;
+    let index_entries = parse_index(&index)?;
+    let store = read_vec(r, header.store_size as usize, "store")?;
+    let pos = advance(pos, padding + HEADER_SIZE + index_size + store.len())?;
+    Ok((pos, FullHeader { header, index_entries, index, store }))
+}
+
+pub type SyncPayload = Box<Read + Send + 'static>;
+
+/// Blocking counterpart of read_all_headers().
+pub struct RpmReader {
+    pub lead: Lead,
+    pub signature_header: FullHeader,
+    pub header: FullHeader,
+    payload: SyncPayload,
+    verifier: Verifier,
+}
+
+impl RpmReader {
+    /// Reads the headers and checks the header digests.
+    pub fn new<R: Read + Send + 'static>(mut r: R) -> Result<RpmReader, Error> {
+        let (_, lead) = parse_lead(&read_vec(&mut r, LEAD_SIZE, "lead")?)
+            .map_err(|_| format_err!("Could not parse RPM lead - bad magic?"))?;
+        let (pos, signature_header) = read_full_header_sync(&mut r, LEAD_SIZE)?;
+        let (_, header) = read_full_header_sync(&mut r, pos)?;
+        let (r, uncompressed_digests, verifier) = make_verifier(r, &signature_header, &header)?;
+        verifier.verify_header()?;
+        let coding = get_payload_coding(&header)?;
+        let payload: SyncPayload = decode_payload!(r, coding);
+        let payload = Box::new(DigestReader::new(payload, uncompressed_digests));
+        Ok(RpmReader { lead, signature_header, header, payload, verifier })
+    }
+
+    /// The decompressed cpio payload. Pass it to verify_payload_sync() once
+    /// it is read.
+    pub fn into_payload(self) -> (SyncPayload, Verifier) {
+        (self.payload, self.verifier)
+    }
+
+    /// The cpio entries of the payload. Pass the result of into_inner() to
+    /// verify_payload_sync() once they are read.
+    pub fn into_archive(self) -> Result<(Archive<SyncPayload>, Verifier), Error> {
+        let files = FileInfo::from_header(&self.header)?;
+        Ok((Archive::new(self.payload, files), self.verifier))
+    }
+}
+
+/// Blocking counterpart of verify_payload().
+pub fn verify_payload_sync<R: Read>(mut r: R, verifier: Verifier) -> Result<(), Error> {
+    io::copy(&mut r, &mut io::sink()).context("Could not read the end of RPM payload")?;
+    verifier.verify_payload()
 }
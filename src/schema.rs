@@ -15,6 +15,19 @@ table! {
         version -> Text,
         epoch -> Text,
         release -> Text,
+        summary -> Nullable<Text>,
+        description -> Nullable<Text>,
+        license -> Nullable<Text>,
+        url -> Nullable<Text>,
+        vendor -> Nullable<Text>,
+        packager -> Nullable<Text>,
+        buildhost -> Nullable<Text>,
+        buildtime -> Nullable<BigInt>,
+        sourcerpm -> Nullable<Text>,
+        package_group -> Nullable<Text>,
+        installed_size -> Nullable<BigInt>,
+        signature_key_id -> Nullable<Text>,
+        signature_status -> Nullable<Text>,
     }
 }
 
@@ -25,6 +38,15 @@ table! {
         id -> Integer,
         name -> Text,
         package_id -> Integer,
+        mode -> Nullable<Integer>,
+        size -> Nullable<BigInt>,
+        user_name -> Nullable<Text>,
+        group_name -> Nullable<Text>,
+        mtime -> Nullable<BigInt>,
+        digest -> Nullable<Text>,
+        link_to -> Nullable<Text>,
+        flags -> Nullable<Integer>,
+        build_id -> Nullable<Text>,
     }
 }
 
@@ -44,18 +66,129 @@ table! {
         name_id -> Integer,
         st_info -> Integer,
         st_other -> Integer,
+        source -> Text,
+        version -> Nullable<Text>,
+        version_default -> Bool,
+        version_hidden -> Bool,
+        st_shndx -> Nullable<Integer>,
+        st_value -> Nullable<BigInt>,
+        st_size -> Nullable<BigInt>,
+        defined -> Nullable<Bool>,
     }
 }
 
 joinable!(elf_symbols -> files (file_id));
 joinable!(elf_symbols -> strings (name_id));
 
+table! {
+    // Only for strings that are ELF symbol names
+    demangled_names (name_id) {
+        name_id -> Integer,
+        demangled -> Text,
+        qualified_name -> Text,
+    }
+}
+
+joinable!(demangled_names -> strings (name_id));
+
+table! {
+    elf_files (id) {
+        id -> Integer,
+        file_id -> Integer,
+        machine -> Text,
+        class -> Integer,
+        endianness -> Text,
+        elf_type -> Text,
+        os_abi -> Text,
+        interpreter -> Nullable<Text>,
+        pie -> Bool,
+    }
+}
+
+joinable!(elf_files -> files (file_id));
+
+table! {
+    elf_dynamic_entries (id) {
+        id -> Integer,
+        file_id -> Integer,
+        kind -> Text,
+        value_id -> Integer,
+    }
+}
+
+joinable!(elf_dynamic_entries -> files (file_id));
+joinable!(elf_dynamic_entries -> strings (value_id));
+
+table! {
+    dependencies (id) {
+        id -> Integer,
+        package_id -> Integer,
+        kind -> Text,
+        name_id -> Integer,
+        flags -> Integer,
+        version -> Text,
+    }
+}
+
+joinable!(dependencies -> packages (package_id));
+joinable!(dependencies -> strings (name_id));
+
+table! {
+    source_files (id) {
+        id -> Integer,
+        package_id -> Integer,
+        kind -> Text,
+        name -> Text,
+        size -> Nullable<BigInt>,
+        digest -> Nullable<Text>,
+    }
+}
+
+joinable!(source_files -> packages (package_id));
+
+table! {
+    spec_files (id) {
+        id -> Integer,
+        package_id -> Integer,
+        name -> Text,
+        content -> Text,
+    }
+}
+
+joinable!(spec_files -> packages (package_id));
+
+table! {
+    // This is an FTS5 table, rowid is spec_files.id
+    spec_files_fts (rowid) {
+        rowid -> Integer,
+        content -> Text,
+    }
+}
+
+joinable!(spec_files_fts -> spec_files (rowid));
+
+table! {
+    // FIXME: This is a view, so using an arbitrary column to make Diesel happy
+    source_packages (package_id) {
+        package_id -> Integer,
+        source_package_id -> Integer,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(
     repos,
     packages,
     files,
     strings,
+    demangled_names,
+    elf_files,
     elf_symbols,
+    elf_dynamic_entries,
+    dependencies,
+    source_files,
+    spec_files,
+    spec_files_fts,
+    source_packages,
 );
 
 table! {
@@ -0,0 +1,182 @@
+use std::fs::{File, read_dir};
+use std::io::Read;
+use std::path::Path;
+
+use failure::{Error, format_err, ResultExt};
+use log::warn;
+use pgp::composed::{Deserializable, SignedPublicKey};
+use pgp::packet::{Packet, PacketParser, Signature, Subpacket};
+use pgp::types::KeyTrait;
+
+use crate::rpm::{FullHeader, SIGTAG_DSA, SIGTAG_GPG, SIGTAG_PGP, SIGTAG_RSA};
+
+#[derive(Default)]
+pub struct Keyring {
+    keys: Vec<SignedPublicKey>,
+}
+
+impl Keyring {
+    /// Loads every armored public key from `path`, e.g. /etc/pki/rpm-gpg.
+    /// Files that do not contain keys are skipped with a warning.
+    pub fn load_dir(path: &Path) -> Result<Keyring, Error> {
+        let mut keys = Vec::new();
+        let entries = read_dir(path)
+            .with_context(|_| format!("read_dir({:?}) failed", path))?;
+        for entry in entries {
+            let entry_path = entry
+                .with_context(|_| format!("read_dir({:?}) failed", path))?
+                .path();
+            if !entry_path.is_file() {
+                continue;
+            }
+            let file = File::open(&entry_path)
+                .with_context(|_| format!("File::open({:?}) failed", entry_path))?;
+            let parsed = SignedPublicKey::from_armor_many(file)
+                .map_err(|e| format_err!("{:?}", e))
+                .and_then(|(keys, _)| keys
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format_err!("{:?}", e)));
+            match parsed {
+                Ok(t) => keys.extend(t),
+                Err(e) => warn!("Could not read public keys from {:?}: {}", entry_path, e),
+            }
+        }
+        Ok(Keyring { keys })
+    }
+
+    fn verify<R: Read>(&self, signature: &Signature, data: R) -> SignatureStatus {
+        let issuer = match issuer(signature) {
+            Some(t) => t,
+            None => return SignatureStatus::UnknownKey,
+        };
+        for key in &self.keys {
+            let result = if key.primary_key.key_id().to_vec() == issuer {
+                signature.verify(&key.primary_key, data)
+            } else {
+                match key.public_subkeys.iter().find(|s| s.key.key_id().to_vec() == issuer) {
+                    Some(subkey) => signature.verify(&subkey.key, data),
+                    None => continue,
+                }
+            };
+            return match result {
+                Ok(()) => SignatureStatus::Valid,
+                Err(_) => SignatureStatus::Invalid,
+            };
+        }
+        SignatureStatus::UnknownKey
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureStatus {
+    Unsigned,
+    Valid,
+    Invalid,
+    UnknownKey,
+    /// Only a header+payload signature is present, and the payload is not
+    /// available for re-reading.
+    Unverifiable,
+}
+
+impl SignatureStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SignatureStatus::Unsigned => "unsigned",
+            SignatureStatus::Valid => "valid",
+            SignatureStatus::Invalid => "invalid",
+            SignatureStatus::UnknownKey => "unknown-key",
+            SignatureStatus::Unverifiable => "unverifiable",
+        }
+    }
+}
+
+pub struct SignatureCheck {
+    pub key_id: Option<String>,
+    pub status: SignatureStatus,
+}
+
+/// None if there is no such tag.
+fn parse_signature(signature_header: &FullHeader, tag: u32) -> Option<Result<Signature, Error>> {
+    let bytes = match signature_header.get_bin_tag(tag) {
+        Ok(Some(t)) => t,
+        Ok(None) => return None,
+        Err(e) => return Some(Err(e)),
+    };
+    match PacketParser::new(bytes).next() {
+        Some(Ok(Packet::Signature(t))) => Some(Ok(t)),
+        _ => Some(Err(format_err!("RPM signature tag {} is not an OpenPGP signature", tag))),
+    }
+}
+
+/// A malformed signature does not prevent indexing the package, it is just
+/// not valid.
+fn malformed_signature(e: &Error) -> SignatureCheck {
+    warn!("{}", e);
+    SignatureCheck { key_id: None, status: SignatureStatus::Invalid }
+}
+
+/// The issuer key ID. v4 signatures may carry only the issuer fingerprint,
+/// which ends with the key ID, and v5 fingerprints start with it.
+fn issuer(signature: &Signature) -> Option<Vec<u8>> {
+    if let Some(issuer) = signature.issuer() {
+        return Some(issuer.to_vec());
+    }
+    signature.config.hashed_subpackets
+        .iter()
+        .chain(&signature.config.unhashed_subpackets)
+        .find_map(|subpacket| match subpacket {
+            Subpacket::IssuerFingerprint(_, fingerprint) if fingerprint.len() == 20 =>
+                Some(fingerprint[12..].to_vec()),
+            Subpacket::IssuerFingerprint(_, fingerprint) if fingerprint.len() == 32 =>
+                Some(fingerprint[..8].to_vec()),
+            _ => None,
+        })
+}
+
+fn key_id(signature: &Signature) -> Option<String> {
+    issuer(signature).map(hex::encode)
+}
+
+/// Checks RSAHEADER/DSAHEADER against the header. Packages that have only
+/// the legacy PGP/GPG header+payload signatures can be checked only if
+/// `header_and_payload` reads the package starting from the header.
+pub fn check_signatures<R: Read>(
+    keyring: &Keyring,
+    signature_header: &FullHeader,
+    header: &FullHeader,
+    header_and_payload: Option<R>,
+) -> SignatureCheck {
+    let header_bytes = header.raw();
+    let mut first_failure = None;
+    for tag in &[SIGTAG_RSA, SIGTAG_DSA] {
+        let check = match parse_signature(signature_header, *tag) {
+            Some(Ok(signature)) => SignatureCheck {
+                key_id: key_id(&signature),
+                status: keyring.verify(&signature, &header_bytes[..]),
+            },
+            Some(Err(e)) => malformed_signature(&e),
+            None => continue,
+        };
+        if check.status == SignatureStatus::Valid {
+            return check;
+        }
+        first_failure.get_or_insert(check);
+    }
+    if let Some(t) = first_failure {
+        return t;
+    }
+    for tag in &[SIGTAG_PGP, SIGTAG_GPG] {
+        match parse_signature(signature_header, *tag) {
+            Some(Ok(signature)) => {
+                let status = match header_and_payload {
+                    Some(data) => keyring.verify(&signature, data),
+                    None => SignatureStatus::Unverifiable,
+                };
+                return SignatureCheck { key_id: key_id(&signature), status };
+            }
+            Some(Err(e)) => return malformed_signature(&e),
+            None => {}
+        }
+    }
+    SignatureCheck { key_id: None, status: SignatureStatus::Unsigned }
+}
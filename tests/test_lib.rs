@@ -1,8 +1,25 @@
 #[cfg(test)]
 mod test {
+    use std::io::Read;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
     use failure::Error;
+    use goblin::elf::Elf;
+    use goblin::elf::note::NT_GNU_BUILD_ID;
+    use goblin::elf::r#dyn::DT_FLAGS_1;
+    use goblin::elf::section_header::{SHN_ABS, SHT_DYNAMIC, SHT_GNU_VERDEF, SHT_NOTE};
+    use goblin::elf::sym::{st_type, STT_FUNC};
+    use scroll::Endian;
+    use tokio_io::io::Window;
 
+    use index_repo::cpio;
+    use index_repo::elf;
+    use index_repo::fs;
+    use index_repo::hashes;
     use index_repo::repomd;
+    use index_repo::rpm;
+    use index_repo::signatures::{self, SignatureStatus};
 
     #[test]
     fn parse_repomd() -> Result<(), Error> {
@@ -43,4 +60,705 @@ mod test {
         });
         Ok(())
     }
+
+    /// RPM header laid out the way rpm writes it, so that the tests go through
+    /// the real parser.
+    #[derive(Default)]
+    struct HeaderBuilder {
+        count: u32,
+        index: Vec<u8>,
+        store: Vec<u8>,
+    }
+
+    impl HeaderBuilder {
+        /// Adds an index entry as is, which allows making malformed ones.
+        fn entry(mut self, tag: u32, tpe: u32, offset: u32, count: u32) -> Self {
+            for value in &[tag, tpe, offset, count] {
+                self.index.extend_from_slice(&value.to_be_bytes());
+            }
+            self.count += 1;
+            self
+        }
+
+        fn data(mut self, tag: u32, tpe: u32, alignment: usize, count: usize, data: &[u8]) -> Self {
+            while self.store.len() % alignment != 0 {
+                self.store.push(0);
+            }
+            let offset = self.store.len() as u32;
+            self.store.extend_from_slice(data);
+            self.entry(tag, tpe, offset, count as u32)
+        }
+
+        fn strings(self, tag: u32, tpe: u32, values: &[&str]) -> Self {
+            let mut data = Vec::new();
+            for value in values {
+                data.extend_from_slice(value.as_bytes());
+                data.push(0);
+            }
+            self.data(tag, tpe, 1, values.len(), &data)
+        }
+
+        fn string(self, tag: u32, value: &str) -> Self {
+            self.strings(tag, rpm::TYPE_STRING, &[value])
+        }
+
+        fn string_array(self, tag: u32, values: &[&str]) -> Self {
+            self.strings(tag, rpm::TYPE_STRING_ARRAY, values)
+        }
+
+        fn i18n_string(self, tag: u32, values: &[&str]) -> Self {
+            self.strings(tag, rpm::TYPE_I18NSTRING, values)
+        }
+
+        fn bin(self, tag: u32, value: &[u8]) -> Self {
+            self.data(tag, rpm::TYPE_BIN, 1, value.len(), value)
+        }
+
+        fn int16(self, tag: u32, values: &[u16]) -> Self {
+            let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+            self.data(tag, rpm::TYPE_INT16, 2, values.len(), &data)
+        }
+
+        fn int32(self, tag: u32, values: &[u32]) -> Self {
+            let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+            self.data(tag, rpm::TYPE_INT32, 4, values.len(), &data)
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            let mut bytes = vec![0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0];
+            bytes.extend_from_slice(&self.count.to_be_bytes());
+            bytes.extend_from_slice(&(self.store.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&self.index);
+            bytes.extend_from_slice(&self.store);
+            bytes
+        }
+
+        fn build(&self) -> Result<rpm::FullHeader, Error> {
+            let (_, header) = rpm::read_full_header_sync(&mut &self.bytes()[..], 0)?;
+            Ok(header)
+        }
+    }
+
+    /// cpio archive in the newc format or in one of the variants that rpm
+    /// writes, depending on `magic`.
+    struct CpioBuilder {
+        magic: &'static str,
+        bytes: Vec<u8>,
+    }
+
+    impl CpioBuilder {
+        fn new(magic: &'static str) -> CpioBuilder {
+            CpioBuilder { magic, bytes: Vec::new() }
+        }
+
+        fn pad(&mut self) {
+            while self.bytes.len() % 4 != 0 {
+                self.bytes.push(0);
+            }
+        }
+
+        fn entry(mut self, name: &str, ino: u32, mode: u32, nlink: u32, data: &[u8]) -> Self {
+            let checksum = match self.magic {
+                "070702" => data.iter().map(|b| u32::from(*b)).sum(),
+                _ => 0,
+            };
+            let fields = [
+                ino, mode, 0, 0, nlink, 0, data.len() as u32, 253, 0, 0, 0,
+                name.len() as u32 + 1, checksum,
+            ];
+            self.bytes.extend_from_slice(self.magic.as_bytes());
+            for field in &fields {
+                self.bytes.extend_from_slice(format!("{:08x}", field).as_bytes());
+            }
+            self.bytes.extend_from_slice(name.as_bytes());
+            self.bytes.push(0);
+            self.pad();
+            self.bytes.extend_from_slice(data);
+            self.pad();
+            self
+        }
+
+        /// Entry of a stripped archive, which refers to the `fx`th file of the
+        /// RPM header.
+        fn stripped(mut self, fx: u32, data: &[u8]) -> Self {
+            self.bytes.extend_from_slice(format!("07070X{:08x}", fx).as_bytes());
+            self.pad();
+            self.bytes.extend_from_slice(data);
+            self.pad();
+            self
+        }
+
+        fn file(self, name: &str, data: &[u8]) -> Self {
+            self.entry(name, 1, 0o100_644, 1, data)
+        }
+
+        fn build(self) -> Vec<u8> {
+            self.entry("TRAILER!!!", 0, 0, 1, b"").bytes
+        }
+    }
+
+    #[test]
+    fn rpm_typed_tags() -> Result<(), Error> {
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_HEADERI18NTABLE, &["C", "de"])
+            .i18n_string(1004, &["Summary", "Zusammenfassung"])
+            .int32(1028, &[1, 2])
+            .entry(1006, rpm::TYPE_INT32, 36, 2)
+            .build()?;
+        assert_eq!(header.get_i18n_string_tag(1004, "C")?, Some("Summary".to_owned()));
+        assert_eq!(header.get_i18n_string_tag(1004, "de_DE.UTF-8")?,
+                   Some("Zusammenfassung".to_owned()));
+        assert_eq!(header.get_i18n_string_tag(1004, "fr")?, Some("Summary".to_owned()));
+        assert_eq!(header.get_int32_tag(1028)?, Some(vec![1, 2]));
+        assert_eq!(header.get_int32_tag(1000)?, None);
+        assert!(header.get_int32_tag(1006).is_err());
+        assert!(header.get_string_array_tag(1028).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cpio_entry_path() {
+        assert_eq!(cpio::entry_path("./usr/bin/true"), "/usr/bin/true");
+        assert_eq!(cpio::entry_path("usr/bin/true"), "/usr/bin/true");
+        assert_eq!(cpio::entry_path("/usr/bin/true"), "/usr/bin/true");
+    }
+
+    #[test]
+    fn rpm_file_info() -> Result<(), Error> {
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_BASENAMES, &["bin", "true", "false"])
+            .string_array(rpm::TAG_DIRNAMES, &["/usr/", "/usr/bin/"])
+            .int32(rpm::TAG_DIRINDEXES, &[0, 1, 1])
+            .int32(rpm::TAG_FILESIZES, &[4096, 28_000, 28_100])
+            .int16(rpm::TAG_FILEMODES, &[0o040_755, 0o100_755, 0o100_755])
+            .string_array(rpm::TAG_FILEUSERNAME, &["root", "root", "bin"])
+            .build()?;
+        let files = rpm::FileInfo::from_header(&header)?;
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["/usr/bin", "/usr/bin/true", "/usr/bin/false"]);
+        assert_eq!(files[1].mode, 0o100_755);
+        assert_eq!(files[2].size, 28_100);
+        assert_eq!(files[2].user_name, "bin");
+        assert_eq!(files[2].group_name, "");
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_OLDFILENAMES, &["/usr/bin/true"])
+            .build()?;
+        assert_eq!(rpm::FileInfo::from_header(&header)?[0].path, "/usr/bin/true");
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_BASENAMES, &["true"])
+            .string_array(rpm::TAG_DIRNAMES, &["/usr/bin/"])
+            .int32(rpm::TAG_DIRINDEXES, &[1])
+            .build()?;
+        assert!(rpm::FileInfo::from_header(&header).is_err());
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_BASENAMES, &["true", "false"])
+            .string_array(rpm::TAG_DIRNAMES, &["/usr/bin/"])
+            .int32(rpm::TAG_DIRINDEXES, &[0, 0])
+            .int32(rpm::TAG_FILESIZES, &[28_000])
+            .build()?;
+        assert!(rpm::FileInfo::from_header(&header).is_err());
+        Ok(())
+    }
+
+    /// A directory, a fifo, a symlink, two hardlinked files and a %ghost link
+    /// to them, the way a package with a stripped payload could list them.
+    fn stripped_files() -> Result<Vec<rpm::FileInfo>, Error> {
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_BASENAMES, &["dir", "fifo", "link", "a", "b", "ghost"])
+            .string_array(rpm::TAG_DIRNAMES, &["/"])
+            .int32(rpm::TAG_DIRINDEXES, &[0; 6])
+            .int32(rpm::TAG_FILESIZES, &[4096, 7, 1, 5, 5, 5])
+            .int16(rpm::TAG_FILEMODES,
+                   &[0o040_755, 0o010_644, 0o120_777, 0o100_644, 0o100_644, 0o100_644])
+            .int32(rpm::TAG_FILEFLAGS, &[0, 0, 0, 0, 0, rpm::FILE_GHOST])
+            .int32(rpm::TAG_FILEDEVICES, &[1; 6])
+            .int32(rpm::TAG_FILEINODES, &[1, 2, 3, 4, 4, 4])
+            .build()?;
+        rpm::FileInfo::from_header(&header)
+    }
+
+    #[test]
+    fn rpm_file_links() -> Result<(), Error> {
+        let files = stripped_files()?;
+        let nlinks: Vec<u32> = files.iter().map(|f| f.nlink).collect();
+        assert_eq!(nlinks, vec![1, 1, 1, 2, 2, 1]);
+        let has_content: Vec<bool> = files.iter().map(|f| f.has_content).collect();
+        assert_eq!(has_content, vec![false, false, true, false, true, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn rpm_dependencies() -> Result<(), Error> {
+        let tags = [
+            ("provides", rpm::TAG_PROVIDENAME, rpm::TAG_PROVIDEFLAGS, rpm::TAG_PROVIDEVERSION),
+            ("requires", rpm::TAG_REQUIRENAME, rpm::TAG_REQUIREFLAGS, rpm::TAG_REQUIREVERSION),
+            ("conflicts", rpm::TAG_CONFLICTNAME, rpm::TAG_CONFLICTFLAGS,
+             rpm::TAG_CONFLICTVERSION),
+            ("obsoletes", rpm::TAG_OBSOLETENAME, rpm::TAG_OBSOLETEFLAGS,
+             rpm::TAG_OBSOLETEVERSION),
+            ("recommends", rpm::TAG_RECOMMENDNAME, rpm::TAG_RECOMMENDFLAGS,
+             rpm::TAG_RECOMMENDVERSION),
+            ("suggests", rpm::TAG_SUGGESTNAME, rpm::TAG_SUGGESTFLAGS, rpm::TAG_SUGGESTVERSION),
+            ("supplements", rpm::TAG_SUPPLEMENTNAME, rpm::TAG_SUPPLEMENTFLAGS,
+             rpm::TAG_SUPPLEMENTVERSION),
+            ("enhances", rpm::TAG_ENHANCENAME, rpm::TAG_ENHANCEFLAGS, rpm::TAG_ENHANCEVERSION),
+        ];
+        let mut builder = HeaderBuilder::default();
+        let mut expected = Vec::new();
+        for (i, (kind, name_tag, flags_tag, version_tag)) in tags.iter().enumerate() {
+            let name = format!("{}-name", kind);
+            let version = format!("1.{}", i);
+            builder = builder
+                .string_array(*name_tag, &[&name, "other"])
+                .int32(*flags_tag, &[i as u32, 0])
+                .string_array(*version_tag, &[&version, ""]);
+            expected.push((kind.to_string(), name, i as u32, version));
+            expected.push((kind.to_string(), "other".to_owned(), 0, "".to_owned()));
+        }
+        let dependencies = |header: &rpm::FullHeader| -> Result<Vec<_>, Error> {
+            Ok(rpm::Dependency::from_header(header)?.into_iter()
+                .map(|d| (d.kind.to_owned(), d.name, d.flags, d.version))
+                .collect())
+        };
+        assert_eq!(dependencies(&builder.build()?)?, expected);
+        // Flags and versions are optional
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_REQUIRENAME, &["/bin/sh"])
+            .build()?;
+        assert_eq!(dependencies(&header)?,
+                   vec![("requires".to_owned(), "/bin/sh".to_owned(), 0, "".to_owned())]);
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_REQUIRENAME, &["/bin/sh", "libc.so.6"])
+            .int32(rpm::TAG_REQUIREFLAGS, &[0])
+            .build()?;
+        let e = dependencies(&header).err().unwrap();
+        assert!(e.to_string().contains("has 1 values, expected 2"));
+        Ok(())
+    }
+
+    #[test]
+    fn rpm_source_files() -> Result<(), Error> {
+        let header = HeaderBuilder::default()
+            .string_array(rpm::TAG_SOURCE, &["hello-1.0.tar.gz", "hello.desktop"])
+            .string_array(rpm::TAG_PATCH, &["hello-fix.patch"])
+            .string_array(rpm::TAG_BASENAMES,
+                          &["hello-1.0.tar.gz", "hello-fix.patch", "hello.spec"])
+            .string_array(rpm::TAG_DIRNAMES, &[""])
+            .int32(rpm::TAG_DIRINDEXES, &[0, 0, 0])
+            .int32(rpm::TAG_FILESIZES, &[1000, 200, 300])
+            .string_array(rpm::TAG_FILEDIGESTS, &["aa", "bb", "cc"])
+            .build()?;
+        assert!(header.is_source());
+        let files = rpm::FileInfo::from_header(&header)?;
+        let source_files: Vec<_> = rpm::SourceFile::from_header(&header, &files)?
+            .into_iter()
+            .map(|f| (f.kind, f.name, f.size, f.digest))
+            .collect();
+        assert_eq!(source_files, vec![
+            ("source", "hello-1.0.tar.gz".to_owned(), Some(1000), Some("aa".to_owned())),
+            // NoSource
+            ("source", "hello.desktop".to_owned(), None, None),
+            ("patch", "hello-fix.patch".to_owned(), Some(200), Some("bb".to_owned())),
+        ]);
+        let header = HeaderBuilder::default()
+            .string(rpm::TAG_SOURCERPM, "hello-1.0-1.src.rpm")
+            .build()?;
+        assert!(!header.is_source());
+        Ok(())
+    }
+
+    #[test]
+    fn rpm_signatures() -> Result<(), Error> {
+        let keyring = signatures::Keyring::load_dir(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/keys"))?;
+        let header = HeaderBuilder::default().string(rpm::TAG_NAME, "hello").build()?;
+        let check = |signature_header: HeaderBuilder, header: &rpm::FullHeader|
+                     -> Result<_, Error> {
+            let check = signatures::check_signatures(
+                &keyring, &signature_header.build()?, header, None::<&[u8]>);
+            Ok((check.status, check.key_id))
+        };
+        let key_id = Some("352b7aec93b49ab1".to_owned());
+        assert_eq!(check(HeaderBuilder::default(), &header)?, (SignatureStatus::Unsigned, None));
+        // Has no issuer subpacket, only the issuer fingerprint
+        let signature_header = HeaderBuilder::default()
+            .bin(rpm::SIGTAG_RSA, include_bytes!("data/header-fpr.sig"));
+        assert_eq!(check(signature_header, &header)?, (SignatureStatus::Valid, key_id.clone()));
+        let other_header = HeaderBuilder::default().string(rpm::TAG_NAME, "world").build()?;
+        let signature_header = HeaderBuilder::default()
+            .bin(rpm::SIGTAG_RSA, include_bytes!("data/header.sig"));
+        assert_eq!(check(signature_header, &other_header)?,
+                   (SignatureStatus::Invalid, key_id.clone()));
+        let signature_header = HeaderBuilder::default()
+            .bin(rpm::SIGTAG_RSA, b"not a signature");
+        assert_eq!(check(signature_header, &header)?, (SignatureStatus::Invalid, None));
+        let signature_header = HeaderBuilder::default()
+            .bin(rpm::SIGTAG_GPG, include_bytes!("data/header.sig"));
+        assert_eq!(check(signature_header, &header)?, (SignatureStatus::Unverifiable, key_id));
+        Ok(())
+    }
+
+    #[test]
+    fn digest_reader() -> Result<(), Error> {
+        let digest = hashes::SharedDigest::new("sha256")?;
+        let mut reader = hashes::DigestReader::new(&b"abc"[..], vec![digest.clone()]);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"abc");
+        assert_eq!(digest.hexdigest()?,
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        Ok(())
+    }
+
+    fn make_cpio_entry(name: &str, c_ino: u64, c_nlink: u64, c_filesize: u64) -> cpio::Entry {
+        cpio::Entry {
+            header: cpio::Header {
+                c_magic: *b"070701",
+                c_ino,
+                c_mode: 0o100_755,
+                c_uid: 0,
+                c_gid: 0,
+                c_nlink,
+                c_mtime: 0,
+                c_filesize,
+                c_devmajor: 253,
+                c_devminor: 0,
+                c_rdevmajor: 0,
+                c_rdevminor: 0,
+                c_namesize: name.len() as u64 + 1,
+                c_checksum: 0,
+            },
+            name: name.to_owned(),
+            peek: Window::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn cpio_hardlinks() {
+        let mut hardlinks = cpio::Hardlinks::default();
+        assert!(hardlinks.add(&make_cpio_entry("./usr/bin/a", 1, 2, 0)).is_empty());
+        assert_eq!(hardlinks.add(&make_cpio_entry("./usr/bin/c", 2, 1, 100)),
+                   vec!["./usr/bin/c"]);
+        assert_eq!(hardlinks.add(&make_cpio_entry("./usr/bin/b", 1, 2, 100)),
+                   vec!["./usr/bin/a", "./usr/bin/b"]);
+    }
+
+    #[test]
+    fn cpio_archive() -> Result<(), Error> {
+        let bytes = CpioBuilder::new("070701")
+            .file("./a", b"hello")
+            .file("./bb", b"world!")
+            .build();
+        let archive = cpio::Archive::new(&bytes[..], Vec::new());
+        let mut entries = archive.entries();
+        let mut a = entries.next().unwrap()?;
+        assert_eq!(a.name, "./a");
+        let mut buf = [0u8; 2];
+        a.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"he");
+        let mut b = entries.next().unwrap()?;
+        assert_eq!(b.name, "./bb");
+        assert_eq!(b.header.c_filesize, 6);
+        let mut data = Vec::new();
+        b.read_to_end(&mut data)?;
+        assert_eq!(data, b"world!");
+        assert!(a.read(&mut buf).is_err());
+        assert!(entries.next().is_none());
+        assert!(entries.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn cpio_stripped_archive() -> Result<(), Error> {
+        let bytes = CpioBuilder::new("070701")
+            .stripped(0, b"")
+            .stripped(1, b"")
+            .stripped(2, b"a")
+            .stripped(3, b"")
+            .stripped(4, b"hello")
+            .build();
+        let archive = cpio::Archive::new(&bytes[..], stripped_files()?);
+        let mut names = Vec::new();
+        let mut contents = Vec::new();
+        for entry in archive.entries() {
+            let mut entry = entry?;
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            names.push(entry.name);
+            contents.push(data);
+        }
+        assert_eq!(names, vec!["./dir", "./fifo", "./link", "./a", "./b"]);
+        assert_eq!(contents, vec![&b""[..], b"", b"a", b"", b"hello"]);
+        Ok(())
+    }
+
+    #[test]
+    fn cpio_crc_archive() -> Result<(), Error> {
+        let bytes = CpioBuilder::new("070702").file("./a", b"hello").build();
+        let archive = cpio::Archive::new(&bytes[..], Vec::new());
+        let mut entries = archive.entries();
+        let mut a = entries.next().unwrap()?;
+        assert_eq!(&a.header.c_magic, b"070702");
+        assert_eq!(a.header.c_checksum, 532);
+        let mut data = Vec::new();
+        a.read_to_end(&mut data)?;
+        assert_eq!(data, b"hello");
+        assert!(entries.next().is_none());
+
+        let mut bytes = bytes;
+        let offset = bytes.windows(5).position(|w| w == b"hello").unwrap();
+        bytes[offset] = b'j';
+        let archive = cpio::Archive::new(&bytes[..], Vec::new());
+        let mut a = archive.entries().next().unwrap()?;
+        let e = a.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn fs_target_path() -> Result<(), Error> {
+        let directory = tempfile::tempdir()?;
+        let directory = directory.path();
+        assert_eq!(fs::target_path(directory, "/usr/bin/true")?, directory.join("usr/bin/true"));
+        assert!(fs::target_path(directory, "/usr/../../etc/passwd").is_err());
+        std::fs::create_dir(directory.join("usr"))?;
+        symlink("/etc", directory.join("usr/etc"))?;
+        assert!(fs::target_path(directory, "/usr/etc/passwd").is_err());
+        // A symlink itself gets replaced rather than followed
+        assert_eq!(fs::target_path(directory, "/usr/etc")?, directory.join("usr/etc"));
+        Ok(())
+    }
+
+    /// Built by data/elf/Makefile from a version script that defines foo@VERS_1, foo@@VERS_2
+    /// and hello@@VERS_1, and hello() calls puts@GLIBC_2.2.5.
+    static LIBVERSIONS: &[u8] = include_bytes!("data/elf/libversions.so");
+
+    fn function_names<'a>(symbols: &[elf::Symbol<'a>], source: &str) -> Vec<&'a str> {
+        symbols.iter()
+            .filter(|symbol| symbol.source == source && st_type(symbol.st_info) == STT_FUNC)
+            .map(|symbol| symbol.name)
+            .collect()
+    }
+
+    #[test]
+    fn elf_symtab_symbols() -> Result<(), Error> {
+        let elf = Elf::parse(LIBVERSIONS)?;
+        let symbols = elf::symbols(&elf, LIBVERSIONS);
+        assert_eq!(function_names(&symbols, elf::SOURCE_DYNSYM),
+                   vec!["puts", "foo", "foo", "hello"]);
+        // Local symbols are only in .symtab, which spells versions out in the names
+        assert_eq!(function_names(&symbols, elf::SOURCE_SYMTAB),
+                   vec!["foo_v1", "foo_v2", "puts@GLIBC_2.2.5", "foo@VERS_1", "hello",
+                        "foo@@VERS_2"]);
+        Ok(())
+    }
+
+    /// Elf64_Shdr field offsets
+    const SH_OFFSET: usize = 24;
+    const SH_SIZE: usize = 32;
+
+    /// Copies a little-endian ELF64 file with a field of its first `sh_type` section header
+    /// overwritten.
+    fn patch_section_header(
+        bytes: &[u8], sh_type: u32, field: usize, value: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let elf = Elf::parse(bytes)?;
+        let index = elf.section_headers.iter().position(|shdr| shdr.sh_type == sh_type).unwrap();
+        let start = elf.header.e_shoff as usize + index * elf.header.e_shentsize as usize + field;
+        let mut bytes = bytes.to_vec();
+        bytes[start..start + 8].copy_from_slice(&value.to_le_bytes());
+        Ok(bytes)
+    }
+
+    /// Name, version, whether it is the default one and whether it is hidden
+    type FunctionVersion = (String, Option<String>, bool, bool);
+
+    fn function_versions(bytes: &[u8]) -> Result<Vec<FunctionVersion>, Error> {
+        let elf = Elf::parse(bytes)?;
+        Ok(elf::symbols(&elf, bytes).into_iter()
+            .filter(|symbol| symbol.source == elf::SOURCE_DYNSYM)
+            .filter(|symbol| st_type(symbol.st_info) == STT_FUNC)
+            .map(|symbol| (symbol.name.to_owned(), symbol.version.map(str::to_owned),
+                           symbol.version_default, symbol.version_hidden))
+            .collect())
+    }
+
+    #[test]
+    fn elf_symbol_versions() -> Result<(), Error> {
+        let version = |name: &str, version: &str, default, hidden| {
+            (name.to_owned(), Some(version.to_owned()), default, hidden)
+        };
+        assert_eq!(function_versions(LIBVERSIONS)?, vec![
+            // From .gnu.version_r, so never the default
+            version("puts", "GLIBC_2.2.5", false, false),
+            // foo@VERS_1
+            version("foo", "VERS_1", false, true),
+            // foo@@VERS_2
+            version("foo", "VERS_2", true, false),
+            version("hello", "VERS_1", true, false),
+        ]);
+        // Out of bounds .gnu.version_d leaves the symbols without versions
+        let bytes = patch_section_header(LIBVERSIONS, SHT_GNU_VERDEF, SH_SIZE, 1 << 40)?;
+        let names: Vec<_> = function_versions(&bytes)?.into_iter()
+            .map(|(name, version, default, hidden)| {
+                assert_eq!((version, default, hidden), (None, false, false));
+                name
+            })
+            .collect();
+        assert_eq!(names, vec!["puts", "foo", "foo", "hello"]);
+        Ok(())
+    }
+
+    #[test]
+    fn elf_symbol_definitions() -> Result<(), Error> {
+        let elf = Elf::parse(LIBVERSIONS)?;
+        let symbols = elf::symbols(&elf, LIBVERSIONS);
+        let dynsym = |name: &str| symbols.iter()
+            .find(|symbol| symbol.source == elf::SOURCE_DYNSYM && symbol.name == name)
+            .unwrap();
+        let puts = dynsym("puts");
+        assert_eq!((puts.defined, puts.st_shndx, puts.st_value, puts.st_size), (false, 0, 0, 0));
+        let hello = dynsym("hello");
+        assert_eq!((hello.defined, hello.st_shndx, hello.st_value, hello.st_size),
+                   (true, 10, 0x3fc, 12));
+        // Version names are absolute symbols
+        let vers_1 = dynsym("VERS_1");
+        assert_eq!((vers_1.defined, vers_1.st_shndx), (true, SHN_ABS as u16));
+        Ok(())
+    }
+
+    #[test]
+    fn elf_dynamic_entries() -> Result<(), Error> {
+        let entries: Vec<_> = elf::dynamic_entries(&Elf::parse(LIBVERSIONS)?).into_iter()
+            .map(|entry| (entry.kind, entry.value))
+            .collect();
+        assert_eq!(entries, vec![
+            (elf::DYNAMIC_NEEDED, "libc.so.6"),
+            (elf::DYNAMIC_SONAME, "libversions.so.1"),
+            (elf::DYNAMIC_RUNPATH, "$ORIGIN"),
+        ]);
+        Ok(())
+    }
+
+    /// Built by data/elf/Makefile without libc, so it only has an interpreter.
+    static PIE: &[u8] = include_bytes!("data/elf/pie");
+
+    /// Machine, type, interpreter and whether it is a PIE
+    type Properties = (String, String, Option<String>, bool);
+
+    fn elf_properties(bytes: &[u8]) -> Result<Properties, Error> {
+        let properties = elf::properties(&Elf::parse(bytes)?);
+        assert_eq!((properties.class, properties.endianness, properties.os_abi.as_str()),
+                   (64, elf::ENDIANNESS_LITTLE, "SYSV"));
+        Ok((properties.machine, properties.tpe, properties.interpreter.map(str::to_owned),
+            properties.pie))
+    }
+
+    #[test]
+    fn elf_pie() -> Result<(), Error> {
+        let x86_64 = "X86_64".to_owned();
+        let dyn_type = "DYN".to_owned();
+        assert_eq!(elf_properties(LIBVERSIONS)?, (x86_64.clone(), dyn_type.clone(), None, false));
+        let interpreter = Some("/lib64/ld-linux-x86-64.so.2".to_owned());
+        assert_eq!(elf_properties(PIE)?,
+                   (x86_64.clone(), dyn_type.clone(), interpreter.clone(), true));
+        // Without DF_1_PIE, an interpreter and no soname still make a PIE
+        let elf = Elf::parse(PIE)?;
+        let dynamic = elf.section_headers.iter()
+            .find(|shdr| shdr.sh_type == SHT_DYNAMIC)
+            .unwrap();
+        let mut bytes = PIE.to_vec();
+        let start = dynamic.sh_offset as usize;
+        let mut patched = false;
+        for entry in bytes[start..start + dynamic.sh_size as usize].chunks_mut(16) {
+            if entry[..8] == DT_FLAGS_1.to_le_bytes() {
+                entry[8..].copy_from_slice(&0u64.to_le_bytes());
+                patched = true;
+            }
+        }
+        assert!(patched);
+        assert_eq!(elf_properties(&bytes)?, (x86_64, dyn_type, interpreter, true));
+        Ok(())
+    }
+
+    /// Little-endian Elf_Nhdr followed by the name and the descriptor, each padded to
+    /// `alignment`.
+    fn note(name: &[u8], n_type: u32, desc: &[u8], alignment: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&n_type.to_le_bytes());
+        for data in &[name, desc] {
+            bytes.extend_from_slice(data);
+            while bytes.len() % alignment != 0 {
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn elf_build_id_notes() -> Result<(), Error> {
+        for &alignment in &[4, 8] {
+            // Both the name and the descriptor need padding
+            let mut notes = note(b"Linux\0", NT_GNU_BUILD_ID, b"12345", alignment);
+            notes.extend(note(b"GNU\0", NT_GNU_BUILD_ID, b"build-id", alignment));
+            let build_id = elf::find_build_id(&notes, alignment as u64, Endian::Little)?;
+            assert_eq!(build_id, Some(&b"build-id"[..]));
+            assert!(elf::find_build_id(&notes[..notes.len() - 1], alignment as u64,
+                                       Endian::Little).is_err());
+        }
+        let notes = note(b"GNU\0", NT_GNU_BUILD_ID + 1, b"12345", 4);
+        assert_eq!(elf::find_build_id(&notes, 4, Endian::Little)?, None);
+        assert_eq!(elf::find_build_id(&[], 4, Endian::Little)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn elf_build_id() -> Result<(), Error> {
+        let build_id = Some("cd4706c2d64d79a1626aa8b3c067da655149b0a3".to_owned());
+        assert_eq!(elf::build_id(&Elf::parse(LIBVERSIONS)?, LIBVERSIONS), build_id);
+        // An out of bounds .note.gnu.build-id falls back to the PT_NOTE segment
+        let bytes = patch_section_header(LIBVERSIONS, SHT_NOTE, SH_OFFSET, 1 << 40)?;
+        assert_eq!(elf::build_id(&Elf::parse(&bytes)?, &bytes), build_id);
+        Ok(())
+    }
+
+    #[test]
+    fn elf_demangle() {
+        let push_back = "_ZNSt6vectorIiSaIiEE9push_backERKi";
+        assert_eq!(elf::demangle(push_back).unwrap(),
+                   "std::vector<int, std::allocator<int> >::push_back(int const&)");
+        let without_params = elf::demangle_without_params(push_back).unwrap();
+        assert_eq!(without_params, "std::vector<int, std::allocator<int> >::push_back");
+        assert_eq!(elf::qualified_name(&without_params), "std::vector::push_back");
+        assert!(elf::template_args_match(&without_params, &without_params));
+        assert!(elf::template_args_match("std::vector<int>::push_back", &without_params));
+        assert!(!elf::template_args_match("std::vector<long>::push_back", &without_params));
+        assert!(!elf::template_args_match("std::vector::push_back", &without_params));
+        // Operator names are not template argument lists
+        let less = elf::demangle_without_params("_ZN3FooIiEltERKS0_").unwrap();
+        assert_eq!(elf::qualified_name(&less), "Foo::operator<");
+        assert!(elf::template_args_match("Foo<int>::operator<", &less));
+        let write = "_ZN4core3fmt5write17h0123456789abcdefE";
+        assert!(elf::is_rust_legacy(write));
+        assert!(!elf::is_rust_legacy("_ZN4core3fmt5write17h0123456789abcdegE"));
+        assert!(!elf::is_rust_legacy(push_back));
+        assert_eq!(elf::demangle(write).unwrap(), "core::fmt::write");
+        assert_eq!(elf::demangle_without_params(write).unwrap(), "core::fmt::write");
+        assert_eq!(elf::demangle("main"), None);
+    }
+
+    #[test]
+    fn rpm_header_limits() {
+        let mut bytes = vec![0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0];
+        bytes.extend_from_slice(&1u32.to_be_bytes());
+        bytes.extend_from_slice(&0xffff_ffffu32.to_be_bytes());
+        let e = rpm::read_full_header_sync(&mut &bytes[..], 0).err().unwrap();
+        assert!(e.to_string().contains("exceeds the limit"));
+        bytes.truncate(12);
+        bytes.extend_from_slice(&64u32.to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 16]);
+        assert!(rpm::read_full_header_sync(&mut &bytes[..], 0).is_err());
+    }
 }